@@ -0,0 +1,289 @@
+use crate::opcode::{find_instruction, AddressingMode, Instruction};
+use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::Node;
+
+#[derive(Debug, Clone)]
+pub struct AsmToken {
+    pub text: String,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsmLine {
+    pub row: usize,
+    pub label: Option<AsmToken>,
+    pub mnemonic: Option<AsmToken>,
+    pub operand: Option<AsmToken>,
+}
+
+impl AsmLine {
+    pub fn instruction(&self) -> Option<&'static Instruction> {
+        self.mnemonic
+            .as_ref()
+            .and_then(|mnemonic| find_instruction(&mnemonic.text))
+    }
+
+    // branch, jump and subroutine targets that name a label or a function
+    pub fn target(&self) -> Option<&AsmToken> {
+        let instruction = self.instruction()?;
+        let operand = self.operand.as_ref()?;
+        let is_jump = matches!(instruction.mnemonic, "JMP" | "JSR");
+        if (instruction.is_branch() || is_jump) && is_identifier(&operand.text) {
+            Some(operand)
+        } else {
+            None
+        }
+    }
+
+    pub fn addressing_mode(&self) -> Option<AddressingMode> {
+        let instruction = self.instruction()?;
+        let has = |mode: AddressingMode| {
+            instruction
+                .encodings
+                .iter()
+                .any(|encoding| encoding.mode == mode)
+        };
+        if has(AddressingMode::Relative) {
+            return Some(AddressingMode::Relative);
+        }
+        let operand = match self.operand.as_ref() {
+            Some(operand) => operand.text.to_lowercase().replace(' ', ""),
+            None if has(AddressingMode::Accumulator) => return Some(AddressingMode::Accumulator),
+            None => return Some(AddressingMode::Implied),
+        };
+        let mode = if operand == "a" && has(AddressingMode::Accumulator) {
+            AddressingMode::Accumulator
+        } else if operand.starts_with('#') {
+            AddressingMode::Immediate
+        } else if operand.starts_with('(') && operand.ends_with(",x)") {
+            AddressingMode::IndirectX
+        } else if operand.starts_with('(') && operand.ends_with("),y") {
+            AddressingMode::IndirectY
+        } else if operand.starts_with('(') && operand.ends_with(')') {
+            AddressingMode::Indirect
+        } else {
+            let zero_page = is_zero_page(&operand);
+            match (operand.ends_with(",x"), operand.ends_with(",y"), zero_page) {
+                (true, _, true) if has(AddressingMode::ZeroPageX) => AddressingMode::ZeroPageX,
+                (true, _, _) => AddressingMode::AbsoluteX,
+                (_, true, true) if has(AddressingMode::ZeroPageY) => AddressingMode::ZeroPageY,
+                (_, true, _) => AddressingMode::AbsoluteY,
+                (_, _, true) if has(AddressingMode::ZeroPage) => AddressingMode::ZeroPage,
+                _ => AddressingMode::Absolute,
+            }
+        };
+        Some(mode)
+    }
+
+    // (min, max) cycles; page crossings and taken branches only add to max
+    pub fn cycles(&self) -> Option<(u32, u32)> {
+        let mode = self.addressing_mode()?;
+        let encoding = self
+            .instruction()?
+            .encodings
+            .iter()
+            .find(|encoding| encoding.mode == mode)?;
+        let cycles = encoding.cycles as u32;
+        Some(match mode {
+            AddressingMode::Relative => (cycles, cycles + 2),
+            _ if encoding.page_penalty => (cycles, cycles + 1),
+            _ => (cycles, cycles),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AsmBlock {
+    // the label opening the block, or its first instruction when it is entered by falling
+    // through a branch
+    pub start: Option<AsmToken>,
+    pub min_cycles: u32,
+    pub max_cycles: u32,
+}
+
+impl AsmBlock {
+    pub fn cycles_text(&self) -> String {
+        if self.min_cycles == self.max_cycles {
+            format!("{} cycles", self.min_cycles)
+        } else {
+            format!("{}-{} cycles", self.min_cycles, self.max_cycles)
+        }
+    }
+}
+
+fn ends_block(instruction: &Instruction) -> bool {
+    instruction.is_branch() || matches!(instruction.mnemonic, "JMP" | "RTS" | "RTI" | "BRK")
+}
+
+// splits the lines into basic blocks starting at each label and after each branch, jump
+// and return
+pub fn collect_asm_blocks(lines: &[AsmLine]) -> Vec<AsmBlock> {
+    let mut blocks = vec![AsmBlock::default()];
+    let mut after_jump = false;
+    for line in lines {
+        if line.label.is_some() || (after_jump && line.mnemonic.is_some()) {
+            blocks.push(AsmBlock {
+                start: line.label.clone(),
+                ..AsmBlock::default()
+            });
+            after_jump = false;
+        }
+        let Some(instruction) = line.instruction() else {
+            continue;
+        };
+        if let (Some(block), Some((min, max))) = (blocks.last_mut(), line.cycles()) {
+            if block.start.is_none() {
+                block.start = line.mnemonic.clone();
+            }
+            block.min_cycles += min;
+            block.max_cycles += max;
+        }
+        after_jump = ends_block(instruction);
+    }
+    blocks.retain(|block| block.start.is_some());
+    blocks
+}
+
+pub fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+// only literal addresses below $100 are known to be zero page
+fn is_zero_page(operand: &str) -> bool {
+    let address = operand.split(',').next().unwrap_or("");
+    let value = if let Some(hex) = address.strip_prefix('$') {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = address.strip_prefix('%') {
+        u32::from_str_radix(bin, 2).ok()
+    } else {
+        address.parse::<u32>().ok()
+    };
+    matches!(value, Some(value) if value < 0x100)
+}
+
+fn token(row: usize, line: &str, text: &str) -> AsmToken {
+    let start = text.as_ptr() as usize - line.as_ptr() as usize;
+    AsmToken {
+        text: text.to_string(),
+        range: Range {
+            start: Position::new(row as u32, start as u32),
+            end: Position::new(row as u32, (start + text.len()) as u32),
+        },
+    }
+}
+
+pub fn parse_asm_line(row: usize, line: &str) -> AsmLine {
+    let code = match line.find("//") {
+        Some(index) => &line[..index],
+        None => line,
+    };
+    let mut asm_line = AsmLine {
+        row,
+        label: None,
+        mnemonic: None,
+        operand: None,
+    };
+    let mut rest = code.trim();
+    let first = rest.split_whitespace().next().unwrap_or("");
+    if first == "label" {
+        let name = rest[first.len()..].split_whitespace().next().unwrap_or("");
+        let name = name.trim_end_matches(':');
+        if is_identifier(name) {
+            asm_line.label = Some(token(row, line, name));
+        }
+        return asm_line;
+    }
+    if let Some(name) = first.strip_suffix(':') {
+        if is_identifier(name) {
+            asm_line.label = Some(token(row, line, name));
+            rest = rest[first.len()..].trim();
+        }
+    }
+    let mnemonic = rest.split_whitespace().next().unwrap_or("");
+    if find_instruction(mnemonic).is_some() {
+        asm_line.mnemonic = Some(token(row, line, mnemonic));
+        let operand = rest[mnemonic.len()..].trim();
+        if !operand.is_empty() {
+            asm_line.operand = Some(token(row, line, operand));
+        }
+    }
+    asm_line
+}
+
+pub fn parse_asm_function(source: &str, node: &Node) -> Vec<AsmLine> {
+    // the body starts on the line following the signature
+    let first_row = node
+        .child_by_field_name("signature")
+        .map(|signature| signature.end_position().row + 1)
+        .unwrap_or(node.start_position().row + 1);
+    let last_row = match node.end_position() {
+        end if end.column == 0 => end.row.saturating_sub(1),
+        end => end.row,
+    };
+    source
+        .lines()
+        .enumerate()
+        .skip(first_row)
+        .take((last_row + 1).saturating_sub(first_row))
+        .filter(|(_, line)| !line.trim_start().starts_with(':'))
+        .map(|(row, line)| parse_asm_line(row, line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(row: usize, label: Option<&str>, mnemonic: &str, operand: Option<&str>) -> AsmLine {
+        let token = |text: &str| AsmToken {
+            text: text.to_string(),
+            range: Range {
+                start: Position::new(row as u32, 0),
+                end: Position::new(row as u32, text.len() as u32),
+            },
+        };
+        AsmLine {
+            row,
+            label: label.map(token),
+            mnemonic: Some(token(mnemonic)),
+            operand: operand.map(token),
+        }
+    }
+
+    #[test]
+    fn blocks_split_at_labels_and_after_jumps() {
+        let lines = [
+            line(0, None, "ldx", Some("#8")),
+            line(1, Some("loop"), "dex", None),
+            line(2, None, "bne", Some("loop")),
+            line(3, None, "lda", Some("#0")),
+            line(4, None, "rts", None),
+            line(5, None, "nop", None),
+        ];
+        let blocks = collect_asm_blocks(&lines)
+            .into_iter()
+            .map(|block| {
+                (
+                    block.start.unwrap().text,
+                    block.min_cycles,
+                    block.max_cycles,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            [
+                ("ldx".to_string(), 2, 2),
+                ("loop".to_string(), 4, 6),
+                ("lda".to_string(), 8, 8),
+                ("nop".to_string(), 2, 2),
+            ]
+        );
+    }
+}
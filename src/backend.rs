@@ -1,21 +1,69 @@
-use crate::{cfg::collect_cfg_map, symbol::*};
+use crate::{
+    asm::*,
+    builtin::{find_builtin, BuiltinKind, BUILTINS},
+    cfg::{
+        collect_cfg_map, collect_output_path, collect_resource_dirs, input_insertion, CfgProject,
+    },
+    charmap::*,
+    compiler::*,
+    format::{align_table, else_edits, find_table, format_edits, newline_edits, FormatSettings},
+    keyword::{code_context, KEYWORDS},
+    number::{collect_numeric_literals, Base},
+    opcode::*,
+    palette::*,
+    preview::TileSheet,
+    resource::*,
+    symbol::*,
+    usage::{collect_identifier_tokens, identifier_token, FileUsage, IdentifierToken},
+    utf16::{byte_offset, utf16_len, utf16_range},
+};
 use anyhow::{anyhow, Context};
 use dashmap::{DashMap, DashSet};
 use rayon::prelude::*;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock, RwLock,
+    },
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    sync::watch,
 };
 use tower_lsp::{jsonrpc, lsp_types::*, Client, LanguageServer};
-use tree_sitter::{Parser, Point, Tree};
+use tree_sitter::{Node, Parser, Point, Tree};
+
+// diagnostic code of files that no cfg lists as an input
+const ORPHAN_FILE: &str = "orphan-file";
+const UNDEFINED_SYMBOL: &str = "undefined-symbol";
+const UNUSED_SYMBOL: &str = "unused-symbol";
+
+// array and table literals spanning fewer lines than this are not folded
+const MIN_TABLE_FOLDING_ROWS: usize = 3;
+
+// completion lists longer than this are cut short and marked incomplete
+const MAX_COMPLETION_ITEMS: usize = 200;
 
 pub struct Backend {
     pub client: Client,
     pub source_map: DashMap<PathBuf, String>,
     pub tree_map: DashMap<PathBuf, Tree>,
     pub symbol_map: DashMap<PathBuf, SymbolTable>,
-    pub cfg_map: DashMap<PathBuf, HashSet<PathBuf>>,
+    pub usage_map: DashMap<PathBuf, FileUsage>,
+    pub cfg_map: DashMap<PathBuf, CfgProject>,
     pub workspace_dirs: DashSet<PathBuf>,
+    pub client_capabilities: OnceLock<ClientCapabilities>,
+    pub compiler_settings: RwLock<CompilerSettings>,
+    pub format_settings: RwLock<FormatSettings>,
+    pub compiler_diagnostics: DashMap<PathBuf, Vec<Diagnostic>>,
+    // bumped on every save so that pending compiler runs can be cancelled
+    pub compile_generation: watch::Sender<usize>,
+    // numbers the progress tokens the server creates when the client sends none
+    pub progress_counter: AtomicU64,
+    // PRG ROM layout of the last successful build of each cfg
+    pub rom_map: DashMap<PathBuf, (RomInfo, Vec<RomSymbol>)>,
 }
 
 impl Backend {
@@ -29,8 +77,16 @@ impl Backend {
             source_map: DashMap::new(),
             tree_map: DashMap::new(),
             symbol_map: DashMap::new(),
+            usage_map: DashMap::new(),
             cfg_map: DashMap::new(),
             workspace_dirs: DashSet::new(),
+            client_capabilities: OnceLock::new(),
+            compiler_settings: RwLock::new(CompilerSettings::default()),
+            format_settings: RwLock::new(FormatSettings::default()),
+            compiler_diagnostics: DashMap::new(),
+            compile_generation: watch::Sender::new(0),
+            progress_counter: AtomicU64::new(0),
+            rom_map: DashMap::new(),
         }
     }
 
@@ -38,11 +94,52 @@ impl Backend {
         let file_path = file_path.to_owned();
         self.cfg_map
             .iter()
-            .filter(|entry| entry.pair().1.contains(&file_path))
-            .flat_map(|entry| entry.pair().1.to_owned())
+            .filter(|entry| entry.value().inputs.contains(&file_path))
+            .flat_map(|entry| entry.value().inputs.to_owned())
+            .collect()
+    }
+
+    fn get_cfg_file_paths(&self, file_path: &Path) -> Vec<PathBuf> {
+        let mut cfg_file_paths = self
+            .cfg_map
+            .iter()
+            .filter(|entry| entry.value().inputs.contains(file_path))
+            .map(|entry| entry.value().cfg_file_path.to_owned())
+            .collect::<Vec<_>>();
+        cfg_file_paths.sort();
+        cfg_file_paths
+    }
+
+    fn get_resource_dirs(&self, file_path: &Path) -> Vec<PathBuf> {
+        self.cfg_map
+            .iter()
+            .filter(|entry| entry.value().inputs.contains(file_path))
+            .filter_map(|entry| collect_resource_dirs(&entry.value().cfg_file_path).ok())
+            .flatten()
             .collect()
     }
 
+    fn file_includes(&self, file_path: &Path) -> anyhow::Result<Vec<FileInclude>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let file_includes = collect_file_includes(&source)
+            .into_iter()
+            .filter(|file_include| {
+                let point = point_at(&source, file_include.format_range.start);
+                tree.root_node()
+                    .descendant_for_point_range(point, point)
+                    .is_some_and(|node| find_ancestor(node, "comment").is_none())
+            })
+            .collect();
+        Ok(file_includes)
+    }
+
     async fn on_change(&self, params: TextDocumentItem) -> anyhow::Result<()> {
         let file_path = params
             .uri
@@ -63,176 +160,488 @@ impl Backend {
         let mut cursor = root_node.walk();
         let mut symbol_table = SymbolTable::default();
         traverse_tree(source, &mut cursor, &mut symbol_table)?;
-        self.symbol_map.insert(file_path, symbol_table);
+        self.symbol_map.insert(file_path.clone(), symbol_table);
+        self.usage_map
+            .insert(file_path.clone(), FileUsage::from_tree(source, &tree));
+
+        let diagnostics = self.diagnostics(&file_path).await;
+        self.client
+            .publish_diagnostics(params.uri, diagnostics, Some(params.version))
+            .await;
+        self.publish_project_diagnostics(&file_path).await;
 
         Ok(())
     }
 
-    async fn on_change_workspace_folders(
-        &self,
-        event: WorkspaceFoldersChangeEvent,
-    ) -> anyhow::Result<()> {
-        let to_path_buf_set = |workspace_folders: &[WorkspaceFolder]| -> HashSet<PathBuf> {
-            workspace_folders
-                .iter()
-                .map(|dir| dir.uri.to_file_path())
-                .filter_map(|file_path| file_path.ok())
-                .collect::<HashSet<_>>()
-        };
-        let cfg_files = self
-            .cfg_map
-            .iter()
-            .map(|entry| entry.pair().0.to_owned())
-            .collect::<HashSet<PathBuf>>()
-            .difference(&to_path_buf_set(&event.removed))
-            .cloned()
-            .collect::<HashSet<_>>()
-            .union(&to_path_buf_set(&event.added))
-            .cloned()
-            .filter(|path| !self.cfg_map.contains_key(path))
+    // unused and duplicate symbols in one file depend on every other input of its cfg
+    async fn publish_project_diagnostics(&self, file_path: &Path) {
+        let mut file_paths = self
+            .get_dependencies(file_path)
+            .into_iter()
+            .filter(|path| path != file_path && self.source_map.contains_key(path))
             .collect::<Vec<_>>();
-
-        // reconstruct cfg_map
-        let cfg_map = collect_cfg_map(&cfg_files)?;
-        self.cfg_map.clear();
-        for (key, value) in cfg_map.clone() {
-            self.cfg_map.insert(key, value);
-        }
-
-        // reconstruct symbol_map
-        let symbol_map = cfg_map
-            .par_iter()
-            .flat_map(|(_, files)| files)
-            .cloned()
-            .filter(|file| !self.symbol_map.contains_key(file))
-            .collect::<HashSet<_>>()
-            .par_iter()
-            .filter_map(|file| {
-                if let Ok(source) = std::fs::read_to_string(file) {
-                    SymbolTable::from_source(&source)
-                        .map(|symbol_table| (file.to_owned(), symbol_table))
-                        .ok()
-                } else {
-                    None
-                }
-            })
-            .collect::<HashMap<_, _>>();
-        for (key, value) in symbol_map {
+        file_paths.sort();
+        for path in file_paths {
+            let Ok(uri) = Url::from_file_path(&path) else {
+                continue;
+            };
+            let diagnostics = self.diagnostics(&path).await;
             self.client
-                .log_message(MessageType::INFO, format!("symbol cached: {key:?}"))
+                .publish_diagnostics(uri, diagnostics, None)
                 .await;
-            self.symbol_map.insert(key, value);
         }
+    }
 
-        Ok(())
+    fn get_charmaps(&self, file_path: &Path) -> HashMap<String, CharmapSymbol> {
+        let mut dependencies = self.get_dependencies(file_path);
+        dependencies.insert(file_path.to_owned());
+        dependencies
+            .iter()
+            .filter_map(|path| self.symbol_map.get(path))
+            .flat_map(|symbols| symbols.charmaps.clone())
+            .collect()
     }
 
-    async fn completion(
+    // string literals paired with the charmap they are encoded with, if any
+    fn string_literals(
         &self,
         file_path: &Path,
-        _point: &Point,
-    ) -> anyhow::Result<Option<CompletionResponse>> {
-        let dependency_symbols = self
-            .get_dependencies(file_path)
+    ) -> anyhow::Result<Vec<(StringLiteral, Option<CharmapSymbol>)>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let file_includes = collect_file_includes(&source);
+        let charmaps = self.get_charmaps(file_path);
+        let default_charmap = charmaps.values().find(|charmap| charmap.is_default);
+        let string_literals = source
+            .lines()
+            .enumerate()
+            .filter(|(row, line)| parse_charmap_definition(*row, line).is_none())
+            .flat_map(|(row, line)| collect_string_literals(row, line))
+            .filter(|string_literal| {
+                let start = string_literal.range.start;
+                let point = point_at(&source, start);
+                let is_comment = tree
+                    .root_node()
+                    .descendant_for_point_range(point, point)
+                    .is_some_and(|node| find_ancestor(node, "comment").is_some());
+                let is_file_path = file_includes.iter().any(|file_include| {
+                    file_include.path_range.start.line == start.line
+                        && file_include.path_range.start.character == start.character + 1
+                });
+                !is_comment && !is_file_path
+            })
+            .map(|string_literal| {
+                let charmap = match &string_literal.charmap {
+                    Some(name) => charmaps.get(name),
+                    None => default_charmap,
+                };
+                (string_literal, charmap.cloned())
+            })
+            .collect();
+        Ok(string_literals)
+    }
+
+    // providers fail independently so that one error doesn't hide every other diagnostic
+    async fn diagnostics(&self, file_path: &Path) -> Vec<Diagnostic> {
+        let results = [
+            ("label", self.label_diagnostics(file_path)),
+            ("file include", self.file_include_diagnostics(file_path)),
+            ("charmap", self.charmap_diagnostics(file_path)),
+            (
+                "orphan file",
+                Ok(self
+                    .orphan_file_diagnostics(file_path)
+                    .into_iter()
+                    .collect()),
+            ),
+            ("undefined call", self.undefined_call_diagnostics(file_path)),
+            ("unused symbol", self.unused_symbol_diagnostics(file_path)),
+            (
+                "duplicate definition",
+                self.duplicate_definition_diagnostics(file_path),
+            ),
+        ];
+        let mut diagnostics = Vec::new();
+        for (provider, result) in results {
+            match result {
+                Ok(provided) => diagnostics.extend(provided),
+                Err(e) => {
+                    self.client
+                        .log_message(
+                            MessageType::ERROR,
+                            format!("{provider} diagnostics error: {e:?}"),
+                        )
+                        .await;
+                }
+            }
+        }
+        if let Some(compiler_diagnostics) = self.compiler_diagnostics.get(file_path) {
+            diagnostics.extend(compiler_diagnostics.iter().cloned());
+        }
+        diagnostics
+    }
+
+    // files outside every cfg get no dependencies, hence no completion or navigation
+    fn orphan_file_diagnostics(&self, file_path: &Path) -> Option<Diagnostic> {
+        if !self.get_cfg_file_paths(file_path).is_empty() {
+            return None;
+        }
+        Some(Diagnostic {
+            range: Range::default(),
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String(ORPHAN_FILE.to_string())),
+            message: "this file is not an input of any .cfg project, \
+                      so symbols from other files are unavailable"
+                .to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn defined_names(&self, file_path: &Path) -> HashSet<String> {
+        let mut dependencies = self.get_dependencies(file_path);
+        dependencies.insert(file_path.to_owned());
+        dependencies
+            .iter()
+            .filter_map(|path| self.symbol_map.get(path))
+            .flat_map(|symbols| {
+                symbols
+                    .functions
+                    .keys()
+                    .chain(symbols.global_variables.keys())
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn undefined_call_diagnostics(&self, file_path: &Path) -> anyhow::Result<Vec<Diagnostic>> {
+        // without a project every call would look undefined
+        if self.get_cfg_file_paths(file_path).is_empty() {
+            return Ok(Vec::new());
+        }
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let defined_names = self.defined_names(file_path);
+        let diagnostics = collect_nodes(tree.root_node(), "call")
             .into_iter()
-            .filter_map(|file_path| self.symbol_map.get(&file_path))
-            .map(|symbol_map| {
-                let pair = symbol_map.pair();
-                (pair.0.to_owned(), pair.1.clone())
+            .filter(|node| find_ancestor(*node, "asm_function_definition").is_none())
+            .filter_map(|node| {
+                let callee = node
+                    .named_child(0)
+                    .filter(|child| child.kind() == "identifier")?;
+                let name = callee.utf8_text(source.as_bytes()).ok()?;
+                // type names are capitalized, and casts or struct literals look like calls
+                if name.starts_with(|c: char| c.is_ascii_uppercase())
+                    || defined_names.contains(name)
+                    || find_builtin(name).is_some()
+                {
+                    return None;
+                }
+                let arguments = call_arguments(&source[callee.end_byte()..node.end_byte()]);
+                Some(Diagnostic {
+                    range: node_range(&callee),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(NumberOrString::String(UNDEFINED_SYMBOL.to_string())),
+                    message: format!("undefined symbol: {name}"),
+                    data: Some(serde_json::json!({
+                        "name": name,
+                        "arguments": arguments,
+                    })),
+                    ..Default::default()
+                })
             })
-            .collect::<HashMap<_, _>>();
-        let items = dependency_symbols
-            .values()
-            .flat_map(|symbol_table| {
-                let global_variables =
-                    symbol_table
-                        .global_variables
-                        .iter()
-                        .map(|(name, symbol)| CompletionItem {
-                            label: name.to_owned(),
-                            kind: Some(CompletionItemKind::VARIABLE),
-                            documentation: Some(Documentation::MarkupContent(MarkupContent {
-                                kind: MarkupKind::Markdown,
-                                value: format!("{}", symbol.description),
-                            })),
-                            ..Default::default()
-                        });
-                let functions =
-                    symbol_table
-                        .functions
+            .collect();
+        Ok(diagnostics)
+    }
+
+    fn unused_symbol_diagnostics(&self, file_path: &Path) -> anyhow::Result<Vec<Diagnostic>> {
+        // library files outside the workspace are full of symbols the project doesn't use
+        if self.get_cfg_file_paths(file_path).is_empty()
+            || self.get_relative_path(file_path).is_none()
+        {
+            return Ok(Vec::new());
+        }
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?
+            .clone();
+        let mut project_files = self.get_dependencies(file_path);
+        project_files.insert(file_path.to_owned());
+        let mut project_usage = Vec::new();
+        for path in &project_files {
+            // a file that couldn't be indexed may hold the only use of anything
+            let Some(usage) = self.usage_map.get(path) else {
+                return Ok(Vec::new());
+            };
+            project_usage.push(usage.clone());
+        }
+
+        // every function reachable from a root, and every name those functions read
+        let mut references = HashMap::<&str, Vec<&String>>::new();
+        let mut pending = Vec::new();
+        for usage in &project_usage {
+            for (function, names) in &usage.references {
+                references.entry(function).or_default().extend(names);
+            }
+            pending.extend(usage.roots.iter().chain(&usage.top_level_references));
+        }
+        let mut used = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if used.insert(name.as_str()) {
+                pending.extend(references.get(name.as_str()).into_iter().flatten());
+            }
+        }
+        let is_called = |name: &str| {
+            project_usage.iter().any(|usage| {
+                usage.top_level_references.contains(name)
+                    || usage
+                        .references
                         .iter()
-                        .map(|(name, symbol)| CompletionItem {
-                            label: name.to_owned(),
-                            kind: Some(CompletionItemKind::FUNCTION),
-                            documentation: symbol.comments.as_ref().map(|comments| {
-                                Documentation::MarkupContent(MarkupContent {
-                                    kind: MarkupKind::Markdown,
-                                    value: format!(
-                                        "{}\n  -------\n  {}",
-                                        symbol.signature, comments
-                                    ),
-                                })
-                            }),
-                            ..Default::default()
-                        });
-                global_variables.chain(functions)
+                        .any(|(function, names)| function != name && names.contains(name))
             })
-            .collect::<Vec<_>>();
-        Ok(Some(CompletionResponse::Array(items)))
-    }
+        };
 
-    fn hover(&self, file_path: &Path, point: &Point) -> anyhow::Result<Option<Hover>> {
-        match self.find_symbol(file_path, point) {
-            Ok(Some((file_path, symbol))) => {
-                let marked_string = MarkedString::LanguageString(LanguageString {
-                    language: "nesfab".to_string(),
-                    value: symbol.description().to_string(),
-                });
-                let file_path = self.get_relative_path(&file_path).unwrap_or(file_path);
-                let path_marked_string =
-                    MarkedString::from_markdown(file_path.to_string_lossy().into());
+        // fields are resolved through the declared type of the accessed value
+        let mut struct_types = HashMap::<&str, HashMap<&str, &str>>::new();
+        let mut global_types = HashMap::<&str, &str>::new();
+        for usage in &project_usage {
+            for field in &usage.struct_fields {
+                struct_types
+                    .entry(&field.struct_name)
+                    .or_default()
+                    .insert(&field.name, &field.type_name);
+            }
+            for (name, type_name) in &usage.global_types {
+                global_types.insert(name, type_name);
+            }
+        }
+        let mut accessed_fields = HashSet::<(&str, &str)>::new();
+        // accesses on call results and other expressions match the field of any struct
+        let mut unresolved_fields = HashSet::<&str>::new();
+        for access in project_usage.iter().flat_map(|usage| &usage.field_accesses) {
+            let Some((field, path)) = access.path.split_last() else {
+                continue;
+            };
+            let root_type = match &access.local_type {
+                Some(type_name) => Some(type_name.as_str()),
+                None => global_types.get(access.root.as_str()).copied(),
+            };
+            let struct_type = path.iter().fold(root_type, |type_name, name| {
+                struct_types.get(type_name?)?.get(name.as_str()).copied()
+            });
+            match struct_type {
+                Some(type_name) if struct_types.contains_key(type_name) => {
+                    accessed_fields.insert((type_name, field));
+                }
+                _ => {
+                    unresolved_fields.insert(field);
+                }
+            }
+        }
 
-                Ok(Some(Hover {
-                    contents: HoverContents::Array(vec![path_marked_string, marked_string]),
-                    range: None,
-                }))
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let tokens = collect_identifier_tokens(&source, &tree);
+        let name_range = |name: &str, range: &Range| definition_name_range(&tokens, name, range);
+        let unused = |range: Range, delete_range: Range, message: String| Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(UNUSED_SYMBOL.to_string())),
+            message,
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: Some(serde_json::json!({ "deleteRange": delete_range })),
+            ..Default::default()
+        };
+        let whole_lines = |range: &Range| {
+            let end = if range.end.character == 0 {
+                range.end.line
+            } else {
+                range.end.line + 1
+            };
+            Range::new(Position::new(range.start.line, 0), Position::new(end, 0))
+        };
+
+        let mut diagnostics = Vec::new();
+        let Some(symbols) = self.symbol_map.get(file_path) else {
+            return Ok(diagnostics);
+        };
+        for (name, symbol) in &symbols.functions {
+            if used.contains(name.as_str()) {
+                continue;
             }
-            Err(e) => Err(e),
-            _ => Ok(None),
+            let message = if is_called(name) {
+                format!("function `{name}` is only called from unused code")
+            } else {
+                format!("function `{name}` is never called")
+            };
+            diagnostics.push(unused(
+                name_range(name, &symbol.range),
+                whole_lines(&symbol.range),
+                message,
+            ));
         }
+        for (name, symbol) in &symbols.global_variables {
+            if used.contains(name.as_str()) {
+                continue;
+            }
+            let message = if symbol
+                .description
+                .lines()
+                .last()
+                .is_some_and(|line| line.split_whitespace().next() == Some("ct"))
+            {
+                format!("constant `{name}` is never used")
+            } else {
+                format!("global `{name}` is never read")
+            };
+            diagnostics.push(unused(
+                name_range(name, &symbol.range),
+                whole_lines(&symbol.range),
+                message,
+            ));
+        }
+        let usage = self
+            .usage_map
+            .get(file_path)
+            .context(format!("failed to get usage: {file_path:?}"))?;
+        for field in &usage.struct_fields {
+            if accessed_fields.contains(&(field.struct_name.as_str(), field.name.as_str()))
+                || unresolved_fields.contains(field.name.as_str())
+            {
+                continue;
+            }
+            diagnostics.push(unused(
+                field.range,
+                Range::new(Position::new(field.row, 0), Position::new(field.row + 1, 0)),
+                format!(
+                    "field `{}` of `{}` is never accessed",
+                    field.name, field.struct_name
+                ),
+            ));
+        }
+        Ok(diagnostics)
     }
 
-    fn goto_definition(
+    fn duplicate_definition_diagnostics(
         &self,
         file_path: &Path,
-        point: &Point,
-    ) -> anyhow::Result<Option<GotoDefinitionResponse>> {
-        match self.find_symbol(file_path, point) {
-            Ok(Some((file_path, symbol))) => {
-                let url = Url::from_file_path(file_path)
-                    .map_err(|_| anyhow!("failed to convert file path to url"))?;
-                let range = symbol.range();
-                Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
-                    url, range,
-                ))))
+    ) -> anyhow::Result<Vec<Diagnostic>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let Some(symbols) = self.symbol_map.get(file_path) else {
+            return Ok(Vec::new());
+        };
+        let tokens = collect_identifier_tokens(&source, &tree);
+        let names = symbols
+            .functions
+            .keys()
+            .chain(symbols.global_variables.keys())
+            .chain(symbols.shadowed.iter().map(|(name, _)| name))
+            .collect::<BTreeSet<_>>();
+        let dependencies = self
+            .get_dependencies(file_path)
+            .into_iter()
+            .filter(|path| path != file_path)
+            .collect::<Vec<_>>();
+
+        let mut diagnostics = Vec::new();
+        for name in names {
+            let mut definitions = symbols
+                .definition_ranges(name)
+                .into_iter()
+                .map(|range| (file_path.to_owned(), range))
+                .collect::<Vec<_>>();
+            let local_count = definitions.len();
+            for path in &dependencies {
+                if let Some(other) = self.symbol_map.get(path) {
+                    definitions.extend(
+                        other
+                            .definition_ranges(name)
+                            .into_iter()
+                            .map(|range| (path.clone(), range)),
+                    );
+                }
+            }
+            if definitions.len() < 2 {
+                continue;
+            }
+            for (index, (_, range)) in definitions[..local_count].iter().enumerate() {
+                let related_information = definitions
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .filter_map(|(_, (path, range))| {
+                        let uri = Url::from_file_path(path).ok()?;
+                        Some(DiagnosticRelatedInformation {
+                            location: Location::new(uri, *range),
+                            message: format!("`{name}` is also defined here"),
+                        })
+                    })
+                    .collect();
+                diagnostics.push(Diagnostic {
+                    range: definition_name_range(&tokens, name, range),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    message: format!("duplicate definition: {name}"),
+                    related_information: Some(related_information),
+                    ..Default::default()
+                });
             }
-            Err(e) => Err(e),
-            _ => Ok(None),
         }
+        Ok(diagnostics)
     }
 
-    fn get_relative_path(&self, path: &Path) -> Option<PathBuf> {
-        self.workspace_dirs
-            .iter()
-            .find_map(|file_path| path.strip_prefix(file_path.to_owned()).ok())
-            .map(|path| path.to_path_buf())
+    fn unused_symbol_action(
+        &self,
+        uri: &Url,
+        diagnostic: &Diagnostic,
+    ) -> Option<CodeActionOrCommand> {
+        let delete_range = diagnostic
+            .data
+            .as_ref()
+            .and_then(|data| data.get("deleteRange"))
+            .and_then(|range| serde_json::from_value::<Range>(range.clone()).ok())?;
+        Some(CodeActionOrCommand::CodeAction(CodeAction {
+            title: "Remove unused definition".to_string(),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic.clone()]),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(
+                    uri.clone(),
+                    vec![TextEdit::new(delete_range, String::new())],
+                )])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }))
     }
 
-    fn find_symbol(
+    fn undefined_symbol_actions(
         &self,
+        uri: &Url,
         file_path: &Path,
-        point: &Point,
-    ) -> anyhow::Result<Option<(PathBuf, Box<dyn Symbol>)>> {
+        diagnostic: &Diagnostic,
+    ) -> anyhow::Result<Vec<CodeActionOrCommand>> {
         let source = self
             .source_map
             .get(file_path)
@@ -241,35 +650,2211 @@ impl Backend {
             .tree_map
             .get(file_path)
             .context(format!("failed to get tree file: {file_path:?}"))?;
-        let node = tree
-            .root_node()
-            .descendant_for_point_range(*point, *point)
-            .context(format!("failed to get node file: {file_path:?}"))?;
+        let data = diagnostic.data.as_ref();
+        let name = data
+            .and_then(|data| data.get("name"))
+            .and_then(|name| name.as_str())
+            .context("undefined symbol diagnostic has no name")?;
+        let arguments = data
+            .and_then(|data| data.get("arguments"))
+            .and_then(|arguments| arguments.as_array())
+            .map(|arguments| {
+                arguments
+                    .iter()
+                    .filter_map(|argument| argument.as_str())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let action = |title: String, edits: Vec<TextEdit>, is_preferred: bool| {
+            CodeActionOrCommand::CodeAction(CodeAction {
+                title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri.clone(), edits)])),
+                    ..Default::default()
+                }),
+                is_preferred: Some(is_preferred),
+                ..Default::default()
+            })
+        };
+        let mut actions = Vec::new();
 
-        if node.kind() == "identifier" {
-            let name = node.utf8_text(source.as_bytes())?;
-            let pair = self
-                .symbol_map
-                .get(file_path)
-                .and_then(|symbols| {
-                    symbols
-                        .find_symbol(&node, &name)
-                        .map(|symbol| (file_path.to_owned(), symbol))
-                        .ok()
-                })
-                .or(self
-                    .symbol_map
+        let mut candidates = self
+            .defined_names(file_path)
+            .into_iter()
+            .chain(
+                BUILTINS
                     .iter()
-                    .filter(|entry| entry.key() != &file_path)
-                    .find_map(|entry| {
-                        let (path, symbols) = entry.pair();
-                        symbols
-                            .find_symbol(&node, &name)
-                            .map(|symbol| (path.to_owned(), symbol))
-                            .ok()
-                    }));
-            return Ok(pair);
-        } else {
+                    .filter(|builtin| builtin.kind == BuiltinKind::Function)
+                    .map(|builtin| builtin.name.to_string()),
+            )
+            .map(|candidate| (edit_distance(name, &candidate), candidate))
+            .filter(|(distance, _)| *distance <= (name.len() / 3).max(1))
+            .collect::<Vec<_>>();
+        candidates.sort();
+        for (index, (_, candidate)) in candidates.into_iter().take(3).enumerate() {
+            actions.push(action(
+                format!("Change to `{candidate}`"),
+                vec![TextEdit::new(diagnostic.range, candidate)],
+                index == 0,
+            ));
+        }
+        // inserting past the last line needs a newline when the file has none
+        let line_count = source.lines().count();
+        let insertion_prefix = |row: usize| {
+            if row >= line_count && !source.ends_with('\n') {
+                "\n"
+            } else {
+                ""
+            }
+        };
+
+        // the stub goes right after the top-level definition containing the call
+        let point = Point::new(
+            diagnostic.range.start.line as usize,
+            diagnostic.range.start.character as usize,
+        );
+        let mut definition = tree
+            .root_node()
+            .descendant_for_point_range(point, point)
+            .context("failed to get node")?;
+        while let Some(parent) = definition
+            .parent()
+            .filter(|parent| parent.kind() != "module")
+        {
+            definition = parent;
+        }
+        let end = definition.end_position();
+        let row = if end.column == 0 {
+            end.row
+        } else {
+            end.row + 1
+        };
+        let mut parameters: Vec<String> = Vec::new();
+        for (index, argument) in arguments.iter().enumerate() {
+            // plain variable arguments lend their names to the parameters
+            let parameter = if is_identifier(argument)
+                && !parameters.iter().any(|parameter| parameter == argument)
+            {
+                argument.to_string()
+            } else {
+                format!("arg{index}")
+            };
+            parameters.push(parameter);
+        }
+        let parameters = parameters
+            .iter()
+            .map(|parameter| format!("U {parameter}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let position = Position::new(row as u32, 0);
+        let prefix = insertion_prefix(row);
+        actions.push(action(
+            format!("Create function `{name}`"),
+            vec![TextEdit::new(
+                Range::new(position, position),
+                format!("{prefix}\nfn {name}({parameters})\n    return\n"),
+            )],
+            false,
+        ));
+
+        for vars in collect_nodes(tree.root_node(), "vars_definition") {
+            let header = source
+                .lines()
+                .nth(vars.start_position().row)
+                .unwrap_or_default()
+                .trim();
+            let indent = collect_nodes(vars, "variable_definition")
+                .first()
+                .map(|variable| variable.start_position().column)
+                .unwrap_or(4);
+            let end = vars.end_position();
+            let row = if end.column == 0 {
+                end.row
+            } else {
+                end.row + 1
+            };
+            let position = Position::new(row as u32, 0);
+            actions.push(action(
+                format!("Declare `{name}` in `{header}`"),
+                vec![TextEdit::new(
+                    Range::new(position, position),
+                    format!("{}{}U {name}\n", insertion_prefix(row), " ".repeat(indent)),
+                )],
+                false,
+            ));
+        }
+        Ok(actions)
+    }
+
+    async fn code_action(
+        &self,
+        params: &CodeActionParams,
+    ) -> anyhow::Result<Vec<CodeActionOrCommand>> {
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_| anyhow!("failed to convert url to file path"))?;
+        let mut actions = Vec::new();
+        // a failing provider leaves the actions of the others
+        let mut results = Vec::new();
+        for diagnostic in &params.context.diagnostics {
+            match &diagnostic.code {
+                Some(NumberOrString::String(code)) if code == ORPHAN_FILE => {
+                    actions.extend(self.add_to_cfg_actions(&file_path, diagnostic));
+                }
+                Some(NumberOrString::String(code)) if code == UNDEFINED_SYMBOL => {
+                    results.push((
+                        "undefined symbol",
+                        self.undefined_symbol_actions(
+                            &params.text_document.uri,
+                            &file_path,
+                            diagnostic,
+                        ),
+                    ));
+                }
+                Some(NumberOrString::String(code)) if code == UNUSED_SYMBOL => {
+                    actions
+                        .extend(self.unused_symbol_action(&params.text_document.uri, diagnostic));
+                }
+                _ => {}
+            }
+        }
+        results.push((
+            "convert literal",
+            self.convert_literal_actions(&params.text_document.uri, &file_path, params.range),
+        ));
+        results.push((
+            "align table",
+            self.align_table_actions(&params.text_document.uri, &file_path, params.range),
+        ));
+        for (provider, result) in results {
+            match result {
+                Ok(provided) => actions.extend(provided),
+                Err(e) => {
+                    self.client
+                        .log_message(
+                            MessageType::ERROR,
+                            format!("{provider} actions error: {e:?}"),
+                        )
+                        .await;
+                }
+            }
+        }
+        Ok(actions)
+    }
+
+    // converts the literal under the cursor, or every literal inside the selection
+    fn convert_literal_actions(
+        &self,
+        uri: &Url,
+        file_path: &Path,
+        range: Range,
+    ) -> anyhow::Result<Vec<CodeActionOrCommand>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let literals = collect_numeric_literals(&source)
+            .into_iter()
+            .filter(|literal| {
+                if range.start == range.end {
+                    literal.range.start <= range.start && range.start <= literal.range.end
+                } else {
+                    range.start <= literal.range.start && literal.range.end <= range.end
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(Base::ALL
+            .iter()
+            .filter_map(|base| {
+                let edits = literals
+                    .iter()
+                    .filter(|literal| literal.base != *base)
+                    .map(|literal| TextEdit::new(literal.range, literal.to_base(*base)))
+                    .collect::<Vec<_>>();
+                let title = match (literals.as_slice(), edits.as_slice()) {
+                    (_, []) => return None,
+                    ([_], [edit]) => format!("Convert to {} `{}`", base.name(), edit.new_text),
+                    _ => format!("Convert {} literals to {}", edits.len(), base.name()),
+                };
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title,
+                    kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), edits)])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }))
+            })
+            .collect())
+    }
+
+    // one action for the configured column count and one keeping the widest existing row
+    fn align_table_actions(
+        &self,
+        uri: &Url,
+        file_path: &Path,
+        range: Range,
+    ) -> anyhow::Result<Vec<CodeActionOrCommand>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let Some(table) = find_table(&source, range) else {
+            return Ok(Vec::new());
+        };
+        let configured = self
+            .format_settings
+            .read()
+            .map_err(|_| anyhow!("failed to read format settings"))?
+            .table_columns;
+        let mut columns = vec![configured];
+        if table.columns() != configured {
+            columns.push(table.columns());
+        }
+        Ok(columns
+            .into_iter()
+            .filter_map(|columns| {
+                let edit = align_table(&source, &table, columns)?;
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Align table into {columns} columns"),
+                    kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }))
+            })
+            .collect())
+    }
+
+    fn add_to_cfg_actions(
+        &self,
+        file_path: &Path,
+        diagnostic: &Diagnostic,
+    ) -> Vec<CodeActionOrCommand> {
+        let mut cfg_file_paths = self
+            .cfg_map
+            .iter()
+            .map(|entry| entry.value().cfg_file_path.to_owned())
+            .collect::<Vec<_>>();
+        // projects containing the file's directory come first
+        cfg_file_paths.sort_by_key(|cfg_file_path| {
+            let contains_file = cfg_file_path
+                .parent()
+                .is_some_and(|cfg_dir| file_path.starts_with(cfg_dir));
+            (!contains_file, cfg_file_path.clone())
+        });
+        cfg_file_paths
+            .into_iter()
+            .filter_map(|cfg_file_path| {
+                let (position, new_text) = input_insertion(&cfg_file_path, file_path).ok()?;
+                let uri = Url::from_file_path(&cfg_file_path).ok()?;
+                let file_name = cfg_file_path.file_name()?.to_string_lossy().to_string();
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Add to {file_name}"),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(
+                            uri,
+                            vec![TextEdit::new(Range::new(position, position), new_text)],
+                        )])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }))
+            })
+            .collect()
+    }
+
+    // re-reads changed .cfg files so that new inputs are picked up without a restart
+    async fn on_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let mut is_project_changed = false;
+        for change in params.changes {
+            let Ok(path) = change.uri.to_file_path() else {
+                continue;
+            };
+            // open documents are indexed from the editor's buffer instead
+            if path.extension().is_some_and(|extension| extension == "fab")
+                && !self.source_map.contains_key(&path)
+                && !self.get_cfg_file_paths(&path).is_empty()
+            {
+                is_project_changed = true;
+                self.symbol_map.remove(&path);
+                self.usage_map.remove(&path);
+                if change.typ == FileChangeType::DELETED {
+                    continue;
+                }
+                if let Some((symbol_table, usage)) = index_file(&path) {
+                    self.symbol_map.insert(path.clone(), symbol_table);
+                    self.usage_map.insert(path, usage);
+                }
+                continue;
+            }
+            if path.extension().is_none_or(|extension| extension != "cfg") {
+                continue;
+            }
+            is_project_changed = true;
+            if let Some(cfg_dir) = path.parent() {
+                self.cfg_map
+                    .remove_if(cfg_dir, |_, project| project.cfg_file_path == path);
+            }
+            if change.typ == FileChangeType::DELETED {
+                continue;
+            }
+            let Ok(cfg_map) = collect_cfg_map(&[&path]) else {
+                continue;
+            };
+            for (cfg_dir, project) in cfg_map {
+                for file_path in &project.inputs {
+                    if self.symbol_map.contains_key(file_path) {
+                        continue;
+                    }
+                    if let Some((symbol_table, usage)) = index_file(file_path) {
+                        self.symbol_map.insert(file_path.clone(), symbol_table);
+                        self.usage_map.insert(file_path.clone(), usage);
+                    }
+                }
+                self.cfg_map.insert(cfg_dir, project);
+            }
+        }
+        if !is_project_changed {
+            return;
+        }
+        let file_paths = self
+            .source_map
+            .iter()
+            .map(|entry| entry.key().to_owned())
+            .collect::<Vec<_>>();
+        for file_path in file_paths {
+            let Ok(uri) = Url::from_file_path(&file_path) else {
+                continue;
+            };
+            let diagnostics = self.diagnostics(&file_path).await;
+            self.client
+                .publish_diagnostics(uri, diagnostics, None)
+                .await;
+        }
+    }
+
+    fn charmap_diagnostics(&self, file_path: &Path) -> anyhow::Result<Vec<Diagnostic>> {
+        let diagnostics = self
+            .string_literals(file_path)?
+            .into_iter()
+            .flat_map(|(string_literal, charmap)| match charmap {
+                Some(charmap) => string_literal
+                    .characters
+                    .iter()
+                    .filter_map(|(character, range)| {
+                        let message = match charmap.index(*character) {
+                            Some(index) if index <= u8::MAX as usize => return None,
+                            Some(index) => format!(
+                                "character {:?} is at index {index} of charmap {}, \
+                                 past the 256 a byte can encode",
+                                character, charmap.name
+                            ),
+                            None => format!(
+                                "character {:?} is not in charmap {}",
+                                character, charmap.name
+                            ),
+                        };
+                        Some(Diagnostic {
+                            range: *range,
+                            severity: Some(DiagnosticSeverity::ERROR),
+                            message,
+                            ..Default::default()
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+                None => string_literal
+                    .charmap
+                    .iter()
+                    .map(|name| Diagnostic {
+                        range: string_literal.range,
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        message: format!("unknown charmap: {name}"),
+                        ..Default::default()
+                    })
+                    .collect(),
+            })
+            .chain(self.oversized_charmap_diagnostics(file_path))
+            .collect();
+        Ok(diagnostics)
+    }
+
+    fn oversized_charmap_diagnostics(&self, file_path: &Path) -> Vec<Diagnostic> {
+        let Some(symbols) = self.symbol_map.get(file_path) else {
+            return Vec::new();
+        };
+        symbols
+            .charmaps
+            .values()
+            .filter(|charmap| charmap.characters.len() > u8::MAX as usize + 1)
+            .map(|charmap| Diagnostic {
+                range: charmap.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                message: format!(
+                    "charmap {} has {} characters, only the first 256 can be encoded",
+                    charmap.name,
+                    charmap.characters.len()
+                ),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn file_include_diagnostics(&self, file_path: &Path) -> anyhow::Result<Vec<Diagnostic>> {
+        let resource_dirs = self.get_resource_dirs(file_path);
+        let diagnostics = self
+            .file_includes(file_path)?
+            .into_iter()
+            .filter_map(|file_include| {
+                if !file_include.is_known_format() {
+                    Some(Diagnostic {
+                        range: file_include.format_range,
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        message: format!("unknown file format: {}", file_include.format),
+                        ..Default::default()
+                    })
+                } else if resolve_file_include(file_path, &resource_dirs, &file_include.path)
+                    .is_none()
+                {
+                    Some(Diagnostic {
+                        range: file_include.path_range,
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        message: format!("file not found: {}", file_include.path),
+                        ..Default::default()
+                    })
+                } else if !file_include.is_supported_extension() {
+                    Some(Diagnostic {
+                        range: file_include.path_range,
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        message: format!(
+                            "unsupported extension for format {}: {}",
+                            file_include.format, file_include.path
+                        ),
+                        ..Default::default()
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        Ok(diagnostics)
+    }
+
+    fn label_diagnostics(&self, file_path: &Path) -> anyhow::Result<Vec<Diagnostic>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let mut dependencies = self.get_dependencies(file_path);
+        dependencies.insert(file_path.to_owned());
+        let is_global_symbol = |name: &str| {
+            dependencies.iter().any(|path| {
+                self.symbol_map.get(path).is_some_and(|symbols| {
+                    symbols.functions.contains_key(name)
+                        || symbols.global_variables.contains_key(name)
+                })
+            })
+        };
+
+        let mut diagnostics = Vec::new();
+        for node in collect_nodes(tree.root_node(), "asm_function_definition") {
+            let lines = parse_asm_function(&source, &node);
+            let labels = lines
+                .iter()
+                .filter_map(|line| line.label.as_ref())
+                .collect::<Vec<_>>();
+            for (index, label) in labels.iter().enumerate() {
+                if let Some(first) = labels[..index]
+                    .iter()
+                    .find(|other| other.text == label.text)
+                {
+                    diagnostics.push(Diagnostic {
+                        range: label.range,
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        message: format!("duplicate label: {}", label.text),
+                        related_information: Url::from_file_path(file_path).ok().map(|uri| {
+                            vec![DiagnosticRelatedInformation {
+                                location: Location::new(uri, first.range),
+                                message: "first defined here".to_string(),
+                            }]
+                        }),
+                        ..Default::default()
+                    });
+                }
+            }
+            for line in lines.iter() {
+                let (Some(instruction), Some(target)) = (line.instruction(), line.target()) else {
+                    continue;
+                };
+                if labels.iter().any(|label| label.text == target.text) {
+                    continue;
+                }
+                // jumps may also leave the function
+                if !instruction.is_branch() && is_global_symbol(&target.text) {
+                    continue;
+                }
+                diagnostics.push(Diagnostic {
+                    range: target.range,
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    message: format!("undefined label: {}", target.text),
+                    ..Default::default()
+                });
+            }
+        }
+        Ok(diagnostics)
+    }
+
+    async fn on_save(&self, params: DidSaveTextDocumentParams) -> anyhow::Result<()> {
+        let settings = self
+            .compiler_settings
+            .read()
+            .map_err(|_| anyhow!("failed to read compiler settings"))?
+            .clone();
+        if !settings.enabled {
+            return Ok(());
+        }
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_| anyhow!("failed to convert url to file path"))?;
+        let cfg_file_paths = self.get_cfg_file_paths(&file_path);
+        if cfg_file_paths.is_empty() {
+            return Ok(());
+        }
+
+        let mut receiver = self.compile_generation.subscribe();
+        self.compile_generation
+            .send_modify(|generation| *generation += 1);
+        receiver.borrow_and_update();
+        let compile = async {
+            tokio::time::sleep(settings.debounce).await;
+            let mut messages = Vec::new();
+            for cfg_file_path in cfg_file_paths.iter() {
+                let output = run_compiler(&settings.path, cfg_file_path).await?;
+                let cfg_dir = cfg_file_path.parent().unwrap_or(Path::new("."));
+                for stream in [&output.stdout, &output.stderr] {
+                    messages.extend(parse_output(&String::from_utf8_lossy(stream), cfg_dir));
+                }
+            }
+            anyhow::Ok(messages)
+        };
+        // a newer save supersedes this run, dropping the future kills the compiler
+        let messages = tokio::select! {
+            _ = receiver.changed() => return Ok(()),
+            messages = compile => messages?,
+        };
+        self.publish_compiler_diagnostics(&cfg_file_paths, messages)
+            .await
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        let argument = params
+            .arguments
+            .first()
+            .and_then(|argument| argument.as_str())
+            .map(|argument| match Url::parse(argument) {
+                Ok(uri) => uri.to_file_path().unwrap_or_default(),
+                Err(_) => PathBuf::from(argument),
+            });
+        let cfg_file_paths = match (params.command.as_str(), argument) {
+            ("nesfab.build", Some(file_path)) => self.get_cfg_file_paths(&file_path),
+            ("nesfab.buildProject", Some(cfg_file_path)) => {
+                vec![std::fs::canonicalize(&cfg_file_path).unwrap_or(cfg_file_path)]
+            }
+            ("nesfab.buildProject", None) if self.cfg_map.len() == 1 => self
+                .cfg_map
+                .iter()
+                .map(|entry| entry.value().cfg_file_path.to_owned())
+                .collect(),
+            (command, _) => return Err(anyhow!("invalid command arguments: {command}")),
+        };
+        if cfg_file_paths.is_empty() {
+            return Err(anyhow!("no cfg project found"));
+        }
+
+        let token = match params.work_done_progress_params.work_done_token {
+            Some(token) => token,
+            None => {
+                let number = self.progress_counter.fetch_add(1, Ordering::Relaxed);
+                let token = NumberOrString::String(format!("{}:{number}", params.command));
+                // clients without progress support simply ignore the notifications
+                let _ = self
+                    .client
+                    .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                        token: token.clone(),
+                    })
+                    .await;
+                token
+            }
+        };
+        // a token carries a single Begin/End pair, so every cfg reports under one
+        self.progress(
+            &token,
+            WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: "Building".to_string(),
+                cancellable: Some(false),
+                message: None,
+                percentage: None,
+            }),
+        )
+        .await;
+        let mut results = Vec::new();
+        let mut summaries = Vec::new();
+        let mut outcome = Ok(());
+        for cfg_file_path in cfg_file_paths.iter() {
+            match self.build(cfg_file_path, &token).await {
+                Ok((result, summary)) => {
+                    results.push(result);
+                    summaries.push(summary);
+                }
+                Err(e) => {
+                    summaries.push(format!("Build failed: {e}"));
+                    outcome = Err(e);
+                    break;
+                }
+            }
+        }
+        self.progress(
+            &token,
+            WorkDoneProgress::End(WorkDoneProgressEnd {
+                message: Some(summaries.join("\n")),
+            }),
+        )
+        .await;
+        outcome?;
+        Ok(Some(serde_json::Value::Array(results)))
+    }
+
+    // reports on `token`, whose Begin and End are sent by the caller
+    async fn build(
+        &self,
+        cfg_file_path: &Path,
+        token: &ProgressToken,
+    ) -> anyhow::Result<(serde_json::Value, String)> {
+        let settings = self
+            .compiler_settings
+            .read()
+            .map_err(|_| anyhow!("failed to read compiler settings"))?
+            .clone();
+        let project = cfg_file_path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.progress(
+            token,
+            WorkDoneProgress::Report(WorkDoneProgressReport {
+                cancellable: Some(false),
+                message: Some(format!("Building {project}")),
+                percentage: None,
+            }),
+        )
+        .await;
+
+        let mut child = spawn_compiler(&settings.path, cfg_file_path)?;
+        let stdout = child
+            .stdout
+            .take()
+            .context("failed to get compiler stdout")?;
+        let mut stderr = child
+            .stderr
+            .take()
+            .context("failed to get compiler stderr")?;
+        let read_stdout = async {
+            let mut output = String::new();
+            let mut lines = BufReader::new(stdout).lines();
+            while let Some(line) = lines.next_line().await? {
+                self.progress(
+                    token,
+                    WorkDoneProgress::Report(WorkDoneProgressReport {
+                        cancellable: Some(false),
+                        message: Some(line.clone()),
+                        percentage: None,
+                    }),
+                )
+                .await;
+                output += &line;
+                output += "\n";
+            }
+            anyhow::Ok(output)
+        };
+        let read_stderr = async {
+            let mut output = String::new();
+            stderr.read_to_string(&mut output).await?;
+            anyhow::Ok(output)
+        };
+        let (stdout, stderr) = tokio::try_join!(read_stdout, read_stderr)?;
+        let status = child.wait().await?;
+
+        let cfg_dir = cfg_file_path.parent().unwrap_or(Path::new("."));
+        let messages = [stdout, stderr]
+            .iter()
+            .flat_map(|output| parse_output(output, cfg_dir))
+            .collect::<Vec<_>>();
+        let count = |severity: DiagnosticSeverity| {
+            messages
+                .iter()
+                .filter(|message| message.severity == severity)
+                .count()
+        };
+        let (errors, warnings) = (
+            count(DiagnosticSeverity::ERROR),
+            count(DiagnosticSeverity::WARNING),
+        );
+        let rom_info = collect_output_path(&cfg_file_path)
+            .filter(|_| status.success())
+            .and_then(|path| RomInfo::from_file(&path).ok());
+        self.publish_compiler_diagnostics(&[cfg_file_path.to_path_buf()], messages)
+            .await?;
+        if let Some(rom_info) = rom_info.as_ref() {
+            let mlb_path = rom_info.path.with_extension("mlb");
+            let rom_symbols = std::fs::read_to_string(mlb_path)
+                .map(|content| parse_mlb(&content, rom_info.prg_bank_size()))
+                .unwrap_or_default();
+            self.rom_map
+                .insert(cfg_file_path.to_path_buf(), (rom_info.clone(), rom_symbols));
+        }
+
+        let summary = match &rom_info {
+            Some(rom_info) => format!("Built {}", rom_info.summary()),
+            None if status.success() => format!("Built {project}"),
+            None => format!("Build of {project} failed: {errors} errors, {warnings} warnings"),
+        };
+        let message_type = if status.success() {
+            MessageType::INFO
+        } else {
+            MessageType::ERROR
+        };
+        self.client.show_message(message_type, &summary).await;
+
+        let result = serde_json::json!({
+            "cfg": cfg_file_path,
+            "success": status.success(),
+            "errors": errors,
+            "warnings": warnings,
+            "rom": rom_info.as_ref().map(|rom_info| &rom_info.path),
+            "size": rom_info.as_ref().map(|rom_info| rom_info.size),
+            "prgBanks": rom_info.as_ref().map(|rom_info| rom_info.prg_banks),
+            "chrBanks": rom_info.as_ref().map(|rom_info| rom_info.chr_banks),
+            "mapper": rom_info.as_ref().map(|rom_info| rom_info.mapper),
+        });
+        Ok((result, summary))
+    }
+
+    // clients only report changes to files they were asked to watch
+    async fn register_file_watcher(&self) {
+        let is_supported = self
+            .client_capabilities
+            .get()
+            .and_then(|capabilities| capabilities.workspace.as_ref())
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched_files| watched_files.dynamic_registration)
+            .unwrap_or(false);
+        if !is_supported {
+            return;
+        }
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: ["**/*.cfg", "**/*.fab"]
+                .into_iter()
+                .map(|pattern| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(pattern.to_string()),
+                    kind: None,
+                })
+                .collect(),
+        };
+        let registration = Registration {
+            id: "nesfab.fileWatcher".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            self.client
+                .log_message(
+                    MessageType::ERROR,
+                    format!("register file watcher error: {e:?}"),
+                )
+                .await;
+        }
+    }
+
+    async fn progress(&self, token: &ProgressToken, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<notification::Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+
+    async fn publish_compiler_diagnostics(
+        &self,
+        cfg_file_paths: &[PathBuf],
+        messages: Vec<CompilerMessage>,
+    ) -> anyhow::Result<()> {
+        let mut file_paths = cfg_file_paths
+            .iter()
+            .filter_map(|cfg_file_path| self.cfg_map.get(cfg_file_path.parent()?))
+            .flat_map(|entry| entry.value().inputs.to_owned())
+            .filter(|file_path| self.compiler_diagnostics.remove(file_path).is_some())
+            .collect::<HashSet<_>>();
+        for message in messages {
+            file_paths.insert(message.file_path.clone());
+            self.compiler_diagnostics
+                .entry(message.file_path)
+                .or_default()
+                .push(Diagnostic {
+                    range: message.range,
+                    severity: Some(message.severity),
+                    source: Some("nesfab".to_string()),
+                    message: message.message,
+                    ..Default::default()
+                });
+        }
+        for file_path in file_paths {
+            let Ok(uri) = Url::from_file_path(&file_path) else {
+                continue;
+            };
+            let diagnostics = if self.source_map.contains_key(&file_path) {
+                self.diagnostics(&file_path).await
+            } else {
+                self.compiler_diagnostics
+                    .get(&file_path)
+                    .map(|diagnostics| diagnostics.clone())
+                    .unwrap_or_default()
+            };
+            self.client
+                .publish_diagnostics(uri, diagnostics, None)
+                .await;
+        }
+        Ok(())
+    }
+
+    async fn on_change_workspace_folders(
+        &self,
+        event: WorkspaceFoldersChangeEvent,
+    ) -> anyhow::Result<()> {
+        let to_path_buf_set = |workspace_folders: &[WorkspaceFolder]| -> HashSet<PathBuf> {
+            workspace_folders
+                .iter()
+                .map(|dir| dir.uri.to_file_path())
+                .filter_map(|file_path| file_path.ok())
+                .collect::<HashSet<_>>()
+        };
+        let cfg_files = self
+            .cfg_map
+            .iter()
+            .map(|entry| entry.pair().0.to_owned())
+            .collect::<HashSet<PathBuf>>()
+            .difference(&to_path_buf_set(&event.removed))
+            .cloned()
+            .collect::<HashSet<_>>()
+            .union(&to_path_buf_set(&event.added))
+            .cloned()
+            .filter(|path| !self.cfg_map.contains_key(path))
+            .collect::<Vec<_>>();
+
+        // reconstruct cfg_map
+        let cfg_map = collect_cfg_map(&cfg_files)?;
+        self.cfg_map.clear();
+        for (key, value) in cfg_map.clone() {
+            self.cfg_map.insert(key, value);
+        }
+
+        // reconstruct symbol_map
+        let symbol_map = cfg_map
+            .par_iter()
+            .flat_map(|(_, project)| &project.inputs)
+            .cloned()
+            .filter(|file| !self.symbol_map.contains_key(file))
+            .collect::<HashSet<_>>()
+            .par_iter()
+            .filter_map(|file| index_file(file).map(|index| (file.to_owned(), index)))
+            .collect::<HashMap<_, _>>();
+        for (key, (symbol_table, usage)) in symbol_map {
+            self.client
+                .log_message(MessageType::INFO, format!("symbol cached: {key:?}"))
+                .await;
+            self.usage_map.insert(key.clone(), usage);
+            self.symbol_map.insert(key, symbol_table);
+        }
+
+        Ok(())
+    }
+
+    async fn completion(
+        &self,
+        file_path: &Path,
+        point: &Point,
+    ) -> anyhow::Result<Option<CompletionResponse>> {
+        let position = Position::new(point.row as u32, point.column as u32);
+        if let Some(file_include) =
+            self.file_includes(file_path)?
+                .into_iter()
+                .find(|file_include| {
+                    file_include.path_range.start <= position
+                        && position <= file_include.path_range.end
+                })
+        {
+            let typed = position
+                .character
+                .checked_sub(file_include.path_range.start.character)
+                .and_then(|column| byte_offset(&file_include.path, column))
+                .and_then(|offset| file_include.path.get(..offset))
+                .unwrap_or(&file_include.path);
+            let (dir, prefix) = typed.rsplit_once('/').unwrap_or(("", typed));
+            let range = Range {
+                start: Position::new(
+                    position.line,
+                    position.character.saturating_sub(utf16_len(prefix)),
+                ),
+                end: position,
+            };
+            let items = search_dirs(file_path, &self.get_resource_dirs(file_path))
+                .into_iter()
+                .filter_map(|search_dir| std::fs::read_dir(search_dir.join(dir)).ok())
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+                    (name, is_dir)
+                })
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .map(|(name, is_dir)| {
+                    let new_text = if is_dir {
+                        format!("{name}/")
+                    } else {
+                        name.clone()
+                    };
+                    CompletionItem {
+                        label: name,
+                        kind: Some(if is_dir {
+                            CompletionItemKind::FOLDER
+                        } else {
+                            CompletionItemKind::FILE
+                        }),
+                        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, new_text))),
+                        ..Default::default()
+                    }
+                })
+                .collect();
+            return Ok(Some(CompletionResponse::Array(items)));
+        }
+        // documentation is filled in lazily by completion_resolve
+        let items = self
+            .get_dependencies(file_path)
+            .into_iter()
+            .filter_map(|file_path| self.symbol_map.get(&file_path))
+            .flat_map(|entry| {
+                let (path, symbol_table) = entry.pair();
+                let item =
+                    |name: &String, kind: CompletionItemKind, symbol_kind: &str| CompletionItem {
+                        label: name.to_owned(),
+                        kind: Some(kind),
+                        data: Some(serde_json::json!({
+                            "filePath": path,
+                            "kind": symbol_kind,
+                            "name": name,
+                        })),
+                        ..Default::default()
+                    };
+                symbol_table
+                    .global_variables
+                    .keys()
+                    .map(|name| item(name, CompletionItemKind::VARIABLE, "variable"))
+                    .chain(
+                        symbol_table
+                            .functions
+                            .keys()
+                            .map(|name| item(name, CompletionItemKind::FUNCTION, "function")),
+                    )
+                    .chain(
+                        symbol_table
+                            .charmaps
+                            .keys()
+                            .map(|name| item(name, CompletionItemKind::CONSTANT, "charmap")),
+                    )
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let defined_names = items
+            .iter()
+            .map(|item| item.label.clone())
+            .collect::<HashSet<_>>();
+        let items = items
+            .into_iter()
+            .chain(if self.is_in_asm_function(file_path, point) {
+                asm_completion_items()
+            } else {
+                let source = self
+                    .source_map
+                    .get(file_path)
+                    .context(format!("failed to get source file: {file_path:?}"))?;
+                let tree = self
+                    .tree_map
+                    .get(file_path)
+                    .context(format!("failed to get tree file: {file_path:?}"))?;
+                // symbols defined in the project shadow the builtin catalogue
+                builtin_completion_items()
+                    .into_iter()
+                    .filter(|item| !defined_names.contains(&item.label))
+                    .chain(keyword_completion_items(&source, &tree, point))
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        let prefix = self.typed_prefix(file_path, point).to_lowercase();
+        let mut items = items
+            .into_iter()
+            .filter(|item| {
+                item.filter_text
+                    .as_ref()
+                    .unwrap_or(&item.label)
+                    .to_lowercase()
+                    .starts_with(&prefix)
+            })
+            .collect::<Vec<_>>();
+        // the client asks again as the prefix grows when the list was cut short
+        let is_incomplete = items.len() > MAX_COMPLETION_ITEMS;
+        if is_incomplete {
+            items.sort_by(|a, b| {
+                a.label
+                    .len()
+                    .cmp(&b.label.len())
+                    .then(a.label.cmp(&b.label))
+            });
+            items.truncate(MAX_COMPLETION_ITEMS);
+        }
+        Ok(Some(CompletionResponse::List(CompletionList {
+            is_incomplete,
+            items,
+        })))
+    }
+
+    fn typed_prefix(&self, file_path: &Path, point: &Point) -> String {
+        let Some(source) = self.source_map.get(file_path) else {
+            return String::new();
+        };
+        let line = source.lines().nth(point.row).unwrap_or("");
+        // the point's column is the client's UTF-16 column
+        let end = byte_offset(line, point.column as u32).unwrap_or(line.len());
+        let before = &line[..end];
+        let start = before
+            .char_indices()
+            .rfind(|(_, c)| !c.is_ascii_alphanumeric() && *c != '_')
+            .map_or(0, |(index, c)| index + c.len_utf8());
+        before[start..].to_string()
+    }
+
+    fn completion_resolve(&self, mut item: CompletionItem) -> anyhow::Result<CompletionItem> {
+        let Some(data) = item.data.as_ref() else {
+            return Ok(item);
+        };
+        let kind = data.get("kind").and_then(|value| value.as_str());
+        let name = data
+            .get("name")
+            .and_then(|value| value.as_str())
+            .context("completion item has no name")?;
+        let markdown = |value: String| {
+            Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }))
+        };
+        match kind {
+            Some("builtin") => {
+                let builtin = find_builtin(name).context(format!("unknown builtin: {name}"))?;
+                item.documentation = markdown(builtin.documentation.to_string());
+                return Ok(item);
+            }
+            Some("instruction") => {
+                let instruction =
+                    find_instruction(name).context(format!("unknown instruction: {name}"))?;
+                item.documentation = markdown(instruction.markdown());
+                return Ok(item);
+            }
+            _ => {}
+        }
+        let file_path = data
+            .get("filePath")
+            .and_then(|value| value.as_str())
+            .map(PathBuf::from)
+            .context("completion item has no file path")?;
+        let symbol_table = self
+            .symbol_map
+            .get(&file_path)
+            .context(format!("failed to get symbol table: {file_path:?}"))?;
+        let (detail, comments) = match kind {
+            Some("function") => symbol_table
+                .functions
+                .get(name)
+                .map(|symbol| (symbol.signature.clone(), symbol.comments.clone())),
+            Some("variable") => symbol_table
+                .global_variables
+                .get(name)
+                .map(|symbol| (symbol.description.clone(), symbol.comments.clone())),
+            Some("charmap") => symbol_table
+                .charmaps
+                .get(name)
+                .map(|symbol| (symbol.description.clone(), None)),
+            _ => None,
+        }
+        .context(format!("failed to find symbol: {name}"))?;
+        let relative_path = self
+            .get_relative_path(&file_path)
+            .unwrap_or(file_path.clone());
+        let mut value = format!("```nesfab\n{detail}\n```\n");
+        if let Some(comments) = comments {
+            value.push_str(&format!("{comments}\n\n"));
+        }
+        value.push_str(&format!("*{}*", relative_path.to_string_lossy()));
+        item.detail = Some(detail);
+        item.documentation = markdown(value);
+        Ok(item)
+    }
+
+    fn hover(&self, file_path: &Path, point: &Point) -> anyhow::Result<Option<Hover>> {
+        if let Some(hover) = self.file_include_hover(file_path, point)? {
+            return Ok(Some(hover));
+        }
+        if let Some(hover) = self.string_literal_hover(file_path, point)? {
+            return Ok(Some(hover));
+        }
+        if let Some(hover) = self.numeric_literal_hover(file_path, point)? {
+            return Ok(Some(hover));
+        }
+        if let Some(instruction) = self.find_instruction(file_path, point)? {
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: instruction.markdown(),
+                }),
+                range: None,
+            }));
+        }
+        match self.find_symbol(file_path, point) {
+            Ok(Some((file_path, symbol))) => {
+                let marked_string = MarkedString::LanguageString(LanguageString {
+                    language: "nesfab".to_string(),
+                    value: symbol.description().to_string(),
+                });
+                let file_path = self.get_relative_path(&file_path).unwrap_or(file_path);
+                let path_marked_string =
+                    MarkedString::from_markdown(file_path.to_string_lossy().into());
+
+                Ok(Some(Hover {
+                    contents: HoverContents::Array(vec![path_marked_string, marked_string]),
+                    range: None,
+                }))
+            }
+            Err(e) => Err(e),
+            _ => self.builtin_hover(file_path, point),
+        }
+    }
+
+    fn builtin_hover(&self, file_path: &Path, point: &Point) -> anyhow::Result<Option<Hover>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let Some(node) = tree
+            .root_node()
+            .descendant_for_point_range(*point, *point)
+            .filter(|node| node.child_count() == 0 && node.kind() != "comment")
+        else {
+            return Ok(None);
+        };
+        let name = node.utf8_text(source.as_bytes())?;
+        Ok(find_builtin(name).map(|builtin| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: builtin.markdown(),
+            }),
+            range: None,
+        }))
+    }
+
+    fn file_include_hover(&self, file_path: &Path, point: &Point) -> anyhow::Result<Option<Hover>> {
+        let position = Position::new(point.row as u32, point.column as u32);
+        let Some(file_include) = self
+            .file_includes(file_path)?
+            .into_iter()
+            .find(|file_include| {
+                file_include.format_range.start <= position
+                    && position <= file_include.path_range.end
+            })
+        else {
+            return Ok(None);
+        };
+        let Some(path) = resolve_file_include(
+            file_path,
+            &self.get_resource_dirs(file_path),
+            &file_include.path,
+        ) else {
+            return Ok(None);
+        };
+        let tile_sheet = match TileSheet::load(&path) {
+            Ok(tile_sheet) if tile_sheet.height > 0 => tile_sheet,
+            _ => return Ok(None),
+        };
+        let file_size = std::fs::metadata(&path)?.len();
+        let summary = format!(
+            "**{}**\n\n{} tiles, {}x{} px, {} bytes",
+            file_include.path,
+            tile_sheet.tile_count,
+            tile_sheet.width,
+            tile_sheet.height,
+            file_size
+        );
+        let preview = if self.supports_markdown_hover() {
+            format!(
+                "![preview]({})",
+                tile_sheet.to_data_uri(&GRAYSCALE_PALETTE)?
+            )
+        } else {
+            format!("```\n{}```", tile_sheet.to_ascii())
+        };
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("{summary}\n\n{preview}"),
+            }),
+            range: Some(file_include.path_range),
+        }))
+    }
+
+    fn string_literal_hover(
+        &self,
+        file_path: &Path,
+        point: &Point,
+    ) -> anyhow::Result<Option<Hover>> {
+        let position = Position::new(point.row as u32, point.column as u32);
+        let Some((string_literal, Some(charmap))) = self
+            .string_literals(file_path)?
+            .into_iter()
+            .find(|(string_literal, _)| {
+                string_literal.range.start <= position && position <= string_literal.range.end
+            })
+        else {
+            return Ok(None);
+        };
+        let bytes = string_literal
+            .characters
+            .iter()
+            .map(|(character, _)| *character)
+            .chain(charmap.sentinel)
+            .map(|character| match charmap.encode(character) {
+                Some(byte) => format!("${byte:02X}"),
+                None => "??".to_string(),
+            })
+            .collect::<Vec<_>>();
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!(
+                    "charmap `{}` ({} bytes)\n\n`{}`",
+                    charmap.name,
+                    bytes.len(),
+                    bytes.join(" ")
+                ),
+            }),
+            range: Some(string_literal.range),
+        }))
+    }
+
+    fn numeric_literal_hover(
+        &self,
+        file_path: &Path,
+        point: &Point,
+    ) -> anyhow::Result<Option<Hover>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let position = Position::new(point.row as u32, point.column as u32);
+        let Some(literal) = collect_numeric_literals(&source)
+            .into_iter()
+            .find(|literal| literal.range.start <= position && position < literal.range.end)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: literal.markdown(),
+            }),
+            range: Some(literal.range),
+        }))
+    }
+
+    fn supports_markdown_hover(&self) -> bool {
+        self.client_capabilities
+            .get()
+            .and_then(|capabilities| capabilities.text_document.as_ref())
+            .and_then(|text_document| text_document.hover.as_ref())
+            .and_then(|hover| hover.content_format.as_ref())
+            .is_some_and(|formats| formats.contains(&MarkupKind::Markdown))
+    }
+
+    fn goto_definition(
+        &self,
+        file_path: &Path,
+        point: &Point,
+    ) -> anyhow::Result<Option<GotoDefinitionResponse>> {
+        if let Some((name, lines)) = self.find_label(file_path, point)? {
+            let url = Url::from_file_path(file_path)
+                .map_err(|_| anyhow!("failed to convert file path to url"))?;
+            let label = lines
+                .iter()
+                .filter_map(|line| line.label.as_ref())
+                .find(|label| label.text == name)
+                .context(format!("failed to find label: {name}"))?;
+            return Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
+                url,
+                label.range,
+            ))));
+        }
+        match self.find_symbol(file_path, point) {
+            Ok(Some((file_path, symbol))) => {
+                let url = Url::from_file_path(file_path)
+                    .map_err(|_| anyhow!("failed to convert file path to url"))?;
+                let range = symbol.range();
+                Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
+                    url, range,
+                ))))
+            }
+            Err(e) => Err(e),
+            _ => Ok(None),
+        }
+    }
+
+    fn references(
+        &self,
+        file_path: &Path,
+        point: &Point,
+        include_declaration: bool,
+    ) -> anyhow::Result<Option<Vec<Location>>> {
+        let Some((name, lines)) = self.find_label(file_path, point)? else {
+            return Ok(None);
+        };
+        let url = Url::from_file_path(file_path)
+            .map_err(|_| anyhow!("failed to convert file path to url"))?;
+        let locations = lines
+            .iter()
+            .flat_map(|line| {
+                let label = line.label.as_ref().filter(|_| include_declaration);
+                label.into_iter().chain(line.target())
+            })
+            .filter(|token| token.text == name)
+            .map(|token| Location::new(url.clone(), token.range))
+            .collect();
+        Ok(Some(locations))
+    }
+
+    fn folding_range(&self, file_path: &Path) -> anyhow::Result<Option<Vec<FoldingRange>>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let lines = source.lines().collect::<Vec<_>>();
+        // trailing blank lines belong to the gap between definitions, not the body
+        let last_code_row = |start: usize, end: usize| {
+            (start..=end.min(lines.len().saturating_sub(1)))
+                .rev()
+                .find(|row| !lines[*row].trim().is_empty())
+                .unwrap_or(start)
+        };
+        let folding_range = |start: usize, end: usize, kind: Option<FoldingRangeKind>| {
+            (end > start).then(|| FoldingRange {
+                start_line: start as u32,
+                end_line: end as u32,
+                kind,
+                ..Default::default()
+            })
+        };
+
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        // fn, mode, asm fn, vars, data and struct definitions are the module's children
+        let definitions = root
+            .named_children(&mut cursor)
+            .filter(|node| node.kind() != "comment")
+            .filter_map(|node| {
+                let end = node.end_position();
+                let end_row = if end.column == 0 {
+                    end.row.saturating_sub(1)
+                } else {
+                    end.row
+                };
+                let start_row = node.start_position().row;
+                folding_range(start_row, last_code_row(start_row, end_row), None)
+            });
+        let comments = collect_nodes(root, "comment")
+            .into_iter()
+            .filter(|node| {
+                // only the last comment of a run starts a backwards collection
+                node.next_sibling().is_none_or(|next| {
+                    next.kind() != "comment"
+                        || next.start_position().row > node.end_position().row + 1
+                })
+            })
+            .filter_map(|node| {
+                let run = collect_sibling_comment_nodes(node);
+                let first = run.last()?;
+                folding_range(
+                    first.start_position().row,
+                    node.end_position().row,
+                    Some(FoldingRangeKind::Comment),
+                )
+            });
+        let tables = collect_bracketed_nodes(root)
+            .into_iter()
+            .map(|node| (node.start_position().row, node.end_position().row))
+            .filter(|(start, end)| end - start >= MIN_TABLE_FOLDING_ROWS)
+            .filter_map(|(start, end)| folding_range(start, end, None));
+
+        let mut folding_ranges = Vec::<FoldingRange>::new();
+        for folding_range in definitions.chain(comments).chain(tables) {
+            let is_duplicate = folding_ranges.iter().any(|other| {
+                other.start_line == folding_range.start_line
+                    && other.end_line == folding_range.end_line
+            });
+            if !is_duplicate {
+                folding_ranges.push(folding_range);
+            }
+        }
+        Ok(Some(folding_ranges))
+    }
+
+    // None when the tree has syntax errors, since the block structure can't be trusted
+    async fn formatting(
+        &self,
+        file_path: &Path,
+        rows: Option<(u32, u32)>,
+    ) -> anyhow::Result<Option<Vec<TextEdit>>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        if tree.root_node().has_error() {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("not formatting {file_path:?}: the file has syntax errors"),
+                )
+                .await;
+            return Ok(None);
+        }
+        Ok(Some(format_edits(&source, rows)))
+    }
+
+    fn on_type_formatting(
+        &self,
+        file_path: &Path,
+        position: Position,
+        ch: &str,
+    ) -> anyhow::Result<Option<Vec<TextEdit>>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let edits = match ch {
+            "\n" => newline_edits(&source, position.line),
+            "e" => {
+                let tree = self
+                    .tree_map
+                    .get(file_path)
+                    .context(format!("failed to get tree file: {file_path:?}"))?;
+                else_edits(&source, &tree, position.line)
+            }
+            _ => Vec::new(),
+        };
+        Ok((!edits.is_empty()).then_some(edits))
+    }
+
+    fn selection_range(
+        &self,
+        file_path: &Path,
+        positions: &[Position],
+    ) -> anyhow::Result<Option<Vec<SelectionRange>>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let selection_ranges = positions
+            .iter()
+            .map(|position| {
+                let point = point_at(&source, *position);
+                let mut ranges = Vec::<Range>::new();
+                let mut pivot = tree.root_node().descendant_for_point_range(point, point);
+                while let Some(node) = pivot {
+                    // punctuation and keywords are anonymous and make no useful step
+                    let range = utf16_range(&source, &node);
+                    if node.is_named() && ranges.last() != Some(&range) {
+                        ranges.push(range);
+                    }
+                    pivot = node.parent();
+                }
+                // built from the outside in so that each parent is the enclosing node
+                let mut selection_range = None;
+                for range in ranges.into_iter().rev() {
+                    selection_range = Some(SelectionRange {
+                        range,
+                        parent: selection_range.map(Box::new),
+                    });
+                }
+                selection_range.unwrap_or(SelectionRange {
+                    range: Range::new(*position, *position),
+                    parent: None,
+                })
+            })
+            .collect();
+        Ok(Some(selection_ranges))
+    }
+
+    fn document_highlight(
+        &self,
+        file_path: &Path,
+        position: Position,
+    ) -> anyhow::Result<Option<Vec<DocumentHighlight>>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let point = point_at(&source, position);
+        if let Some((name, lines)) = self.find_label(file_path, &point)? {
+            let highlights = lines
+                .iter()
+                .flat_map(|line| {
+                    let label = line
+                        .label
+                        .iter()
+                        .map(|label| (label, DocumentHighlightKind::WRITE));
+                    let target = line
+                        .target()
+                        .map(|target| (target, DocumentHighlightKind::READ));
+                    label.chain(target)
+                })
+                .filter(|(token, _)| token.text == name)
+                .map(|(token, kind)| DocumentHighlight {
+                    range: token.range,
+                    kind: Some(kind),
+                })
+                .collect();
+            return Ok(Some(highlights));
+        }
+        let Some(node) = tree
+            .root_node()
+            .descendant_for_point_range(point, point)
+            .filter(|node| node.kind() == "identifier")
+        else {
+            return Ok(None);
+        };
+        let name = node.utf8_text(source.as_bytes())?;
+        let occurrences = collect_nodes(tree.root_node(), "identifier")
+            .into_iter()
+            .filter_map(|node| identifier_token(&source, node).map(|token| (node, token)))
+            .filter(|(_, token)| token.text == name && !token.is_field)
+            .collect::<Vec<_>>();
+        // functions whose parameters or locals shadow the name, with the defining ranges
+        let scopes = FUNCTION_KINDS
+            .iter()
+            .flat_map(|kind| collect_nodes(tree.root_node(), kind))
+            .filter_map(|function| {
+                let definitions = local_definitions(&source, &function, name);
+                (!definitions.is_empty()).then_some((function, definitions))
+            })
+            .collect::<Vec<_>>();
+        let is_within = |scope: &Node, node: &Node| {
+            scope.start_byte() <= node.start_byte() && node.end_byte() <= scope.end_byte()
+        };
+        let (occurrences, definitions) =
+            match scopes.iter().find(|(scope, _)| is_within(scope, &node)) {
+                Some((scope, definitions)) => (
+                    occurrences
+                        .into_iter()
+                        .filter(|(node, _)| is_within(scope, node))
+                        .collect::<Vec<_>>(),
+                    definitions.clone(),
+                ),
+                None => {
+                    let Some((path, _)) = self.find_symbol(file_path, &point)? else {
+                        return Ok(None);
+                    };
+                    // the defining name is the `name` field of its definition node
+                    let definitions = occurrences
+                        .iter()
+                        .filter(|(node, _)| {
+                            path == file_path
+                                && node.parent().is_some_and(|parent| {
+                                    parent.child_by_field_name("name") == Some(*node)
+                                })
+                        })
+                        .map(|(_, token)| token.range)
+                        .collect();
+                    let occurrences = occurrences
+                        .into_iter()
+                        .filter(|(node, _)| !scopes.iter().any(|(scope, _)| is_within(scope, node)))
+                        .collect();
+                    (occurrences, definitions)
+                }
+            };
+        let highlights = occurrences
+            .into_iter()
+            .map(|(_, token)| {
+                let is_write = token.is_write || definitions.contains(&token.range);
+                DocumentHighlight {
+                    range: token.range,
+                    kind: Some(if is_write {
+                        DocumentHighlightKind::WRITE
+                    } else {
+                        DocumentHighlightKind::READ
+                    }),
+                }
+            })
+            .collect();
+        Ok(Some(highlights))
+    }
+
+    // (caller, callee, range of the callee name) for every call in the file
+    fn call_sites(&self, file_path: &Path) -> Vec<(String, String, Range)> {
+        self.usage_map
+            .get(file_path)
+            .map(|usage| usage.call_sites.clone())
+            .unwrap_or_default()
+    }
+
+    fn find_function(&self, file_path: &Path, name: &str) -> Option<(PathBuf, FunctionSymbol)> {
+        let mut file_paths = vec![file_path.to_owned()];
+        file_paths.extend(self.get_dependencies(file_path));
+        file_paths.into_iter().find_map(|path| {
+            let function = self.symbol_map.get(&path)?.functions.get(name)?.clone();
+            Some((path, function))
+        })
+    }
+
+    fn call_hierarchy_item(
+        &self,
+        file_path: &Path,
+        function: &FunctionSymbol,
+    ) -> Option<CallHierarchyItem> {
+        let selection_range = self
+            .usage_map
+            .get(file_path)
+            .and_then(|usage| usage.function_names.get(&function.name).copied())
+            .unwrap_or(function.range);
+        Some(CallHierarchyItem {
+            name: function.name.clone(),
+            kind: SymbolKind::FUNCTION,
+            tags: None,
+            detail: Some(function.signature.clone()),
+            uri: Url::from_file_path(file_path).ok()?,
+            range: function.range,
+            selection_range,
+            data: None,
+        })
+    }
+
+    fn prepare_call_hierarchy(
+        &self,
+        file_path: &Path,
+        point: &Point,
+    ) -> anyhow::Result<Option<Vec<CallHierarchyItem>>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let Some(node) = tree
+            .root_node()
+            .descendant_for_point_range(*point, *point)
+            .filter(|node| node.kind() == "identifier")
+        else {
+            return Ok(None);
+        };
+        let name = node.utf8_text(source.as_bytes())?;
+        Ok(self
+            .find_function(file_path, name)
+            .and_then(|(path, function)| self.call_hierarchy_item(&path, &function))
+            .map(|item| vec![item]))
+    }
+
+    fn incoming_calls(
+        &self,
+        item: &CallHierarchyItem,
+    ) -> anyhow::Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let file_path = item
+            .uri
+            .to_file_path()
+            .map_err(|_| anyhow!("failed to convert url to file path"))?;
+        let mut file_paths = self.get_dependencies(&file_path);
+        file_paths.insert(file_path);
+        let mut calls = Vec::new();
+        for path in file_paths {
+            let mut callers = HashMap::<String, Vec<Range>>::new();
+            for (caller, callee, range) in self.call_sites(&path) {
+                if callee == item.name {
+                    callers.entry(caller).or_default().push(range);
+                }
+            }
+            for (caller, from_ranges) in callers {
+                let from = self
+                    .symbol_map
+                    .get(&path)
+                    .and_then(|symbols| symbols.functions.get(&caller).cloned())
+                    .and_then(|function| self.call_hierarchy_item(&path, &function));
+                if let Some(from) = from {
+                    calls.push(CallHierarchyIncomingCall { from, from_ranges });
+                }
+            }
+        }
+        Ok(Some(calls))
+    }
+
+    fn outgoing_calls(
+        &self,
+        item: &CallHierarchyItem,
+    ) -> anyhow::Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let file_path = item
+            .uri
+            .to_file_path()
+            .map_err(|_| anyhow!("failed to convert url to file path"))?;
+        let mut callees = Vec::<(String, Vec<Range>)>::new();
+        for (caller, callee, range) in self.call_sites(&file_path) {
+            if caller != item.name {
+                continue;
+            }
+            match callees.iter_mut().find(|(name, _)| *name == callee) {
+                Some((_, ranges)) => ranges.push(range),
+                None => callees.push((callee, vec![range])),
+            }
+        }
+        let calls = callees
+            .into_iter()
+            .filter_map(|(callee, from_ranges)| {
+                let (path, function) = self.find_function(&file_path, &callee)?;
+                let to = self.call_hierarchy_item(&path, &function)?;
+                Some(CallHierarchyOutgoingCall { to, from_ranges })
+            })
+            .collect();
+        Ok(Some(calls))
+    }
+
+    fn document_symbol(&self, file_path: &Path) -> anyhow::Result<Option<DocumentSymbolResponse>> {
+        let symbols = self
+            .symbol_map
+            .get(file_path)
+            .context(format!("failed to get symbols: {file_path:?}"))?;
+        #[allow(deprecated)]
+        let mut document_symbols = symbols
+            .functions
+            .values()
+            .map(|function| DocumentSymbol {
+                name: function.name.clone(),
+                detail: Some(function.signature.clone()),
+                kind: SymbolKind::FUNCTION,
+                tags: None,
+                deprecated: None,
+                range: function.range,
+                selection_range: function.range,
+                children: Some(
+                    function
+                        .labels
+                        .iter()
+                        .map(|label| DocumentSymbol {
+                            name: label.name.clone(),
+                            detail: None,
+                            kind: SymbolKind::KEY,
+                            tags: None,
+                            deprecated: None,
+                            range: label.range,
+                            selection_range: label.range,
+                            children: None,
+                        })
+                        .collect(),
+                ),
+            })
+            .chain(
+                symbols
+                    .global_variables
+                    .values()
+                    .map(|variable| DocumentSymbol {
+                        name: variable.name.clone(),
+                        detail: None,
+                        kind: SymbolKind::VARIABLE,
+                        tags: None,
+                        deprecated: None,
+                        range: variable.range,
+                        selection_range: variable.range,
+                        children: None,
+                    }),
+            )
+            .chain(symbols.charmaps.values().map(|charmap| DocumentSymbol {
+                name: charmap.name.clone(),
+                detail: None,
+                kind: SymbolKind::CONSTANT,
+                tags: None,
+                deprecated: None,
+                range: charmap.range,
+                selection_range: charmap.range,
+                children: None,
+            }))
+            .collect::<Vec<_>>();
+        document_symbols
+            .sort_by_key(|symbol| (symbol.range.start.line, symbol.range.start.character));
+        Ok(Some(DocumentSymbolResponse::Nested(document_symbols)))
+    }
+
+    fn document_link(&self, file_path: &Path) -> anyhow::Result<Option<Vec<DocumentLink>>> {
+        let resource_dirs = self.get_resource_dirs(file_path);
+        let document_links = self
+            .file_includes(file_path)?
+            .into_iter()
+            .filter_map(|file_include| {
+                let path = resolve_file_include(file_path, &resource_dirs, &file_include.path)?;
+                Some(DocumentLink {
+                    range: file_include.path_range,
+                    target: Url::from_file_path(&path).ok(),
+                    tooltip: Some(path.to_string_lossy().to_string()),
+                    data: None,
+                })
+            })
+            .collect();
+        Ok(Some(document_links))
+    }
+
+    fn document_color(&self, file_path: &Path) -> anyhow::Result<Vec<ColorInformation>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let lines = source.lines().collect::<Vec<_>>();
+        let color_informations = collect_palette_nodes(&source, tree.root_node())
+            .into_iter()
+            .flat_map(|node| node.start_position().row..=node.end_position().row)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|row| lines.get(row).map(|line| (row, line)))
+            .flat_map(|(row, line)| collect_color_literals(row, line))
+            .map(|(range, index)| {
+                let [red, green, blue] = rgb(index);
+                ColorInformation {
+                    range,
+                    color: Color {
+                        red: red as f32 / 255.0,
+                        green: green as f32 / 255.0,
+                        blue: blue as f32 / 255.0,
+                        alpha: 1.0,
+                    },
+                }
+            })
+            .collect();
+        Ok(color_informations)
+    }
+
+    fn code_lens(&self, file_path: &Path) -> anyhow::Result<Option<Vec<CodeLens>>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let cycles_lens = |range: Range, title: String| CodeLens {
+            range,
+            command: Some(Command {
+                title,
+                command: String::new(),
+                arguments: None,
+            }),
+            data: None,
+        };
+        let code_lenses = collect_nodes(tree.root_node(), "asm_function_definition")
+            .into_iter()
+            .flat_map(|node| {
+                let blocks = collect_asm_blocks(&parse_asm_function(&source, &node));
+                // every instruction once, so loops and skipped branches are not accounted for
+                let max_cycles = blocks.iter().map(|block| block.max_cycles).sum::<u32>();
+                let start = node.start_position();
+                let range = Range {
+                    start: Position::new(start.row as u32, start.column as u32),
+                    end: Position::new(start.row as u32, start.column as u32),
+                };
+                let function_lens = cycles_lens(
+                    range,
+                    format!("straight-line upper bound: {max_cycles} cycles"),
+                );
+                let block_lenses = blocks.into_iter().filter_map(move |block| {
+                    block
+                        .start
+                        .as_ref()
+                        .map(|start| cycles_lens(start.range, block.cycles_text()))
+                });
+                std::iter::once(function_lens).chain(block_lenses)
+            })
+            .chain(self.bank_code_lenses(file_path))
+            .collect();
+        Ok(Some(code_lenses))
+    }
+
+    fn bank_code_lenses(&self, file_path: &Path) -> Vec<CodeLens> {
+        let Some(symbols) = self.symbol_map.get(file_path) else {
+            return Vec::new();
+        };
+        let rom_symbols = self
+            .get_cfg_file_paths(file_path)
+            .iter()
+            .filter_map(|cfg_file_path| self.rom_map.get(cfg_file_path))
+            .flat_map(|entry| entry.value().1.clone())
+            .collect::<Vec<_>>();
+        symbols
+            .functions
+            .values()
+            .filter_map(|function| {
+                let rom_symbol = rom_symbols
+                    .iter()
+                    .find(|rom_symbol| rom_symbol.name == function.name)?;
+                Some(CodeLens {
+                    range: Range {
+                        start: function.range.start,
+                        end: function.range.start,
+                    },
+                    command: Some(Command {
+                        title: format!("bank {}: {} bytes", rom_symbol.bank, rom_symbol.size),
+                        command: String::new(),
+                        arguments: None,
+                    }),
+                    data: None,
+                })
+            })
+            .collect()
+    }
+
+    // `nesfab/bankUsage`: per-bank byte usage for `{ "cfg": path }` or every built project
+    pub async fn bank_usage(
+        &self,
+        params: serde_json::Value,
+    ) -> jsonrpc::Result<serde_json::Value> {
+        let cfg_file_path = params
+            .get("cfg")
+            .and_then(|cfg| cfg.as_str())
+            .map(|cfg| match Url::parse(cfg) {
+                Ok(uri) => uri.to_file_path().unwrap_or_default(),
+                Err(_) => PathBuf::from(cfg),
+            })
+            .map(|path| std::fs::canonicalize(&path).unwrap_or(path));
+        let projects = self
+            .rom_map
+            .iter()
+            .filter(|entry| {
+                cfg_file_path
+                    .as_ref()
+                    .is_none_or(|cfg_file_path| entry.key() == cfg_file_path)
+            })
+            .map(|entry| {
+                let (rom_info, rom_symbols) = entry.value();
+                let bank_size = rom_info.prg_bank_size();
+                let bank_count = (rom_info.prg_banks as u32 * 0x4000).div_ceil(bank_size);
+                let banks = (0..bank_count)
+                    .map(|bank| {
+                        let symbols = rom_symbols
+                            .iter()
+                            .filter(|rom_symbol| rom_symbol.bank == bank)
+                            .map(|rom_symbol| {
+                                serde_json::json!({
+                                    "name": rom_symbol.name,
+                                    "offset": rom_symbol.offset,
+                                    "size": rom_symbol.size,
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        let used = rom_symbols
+                            .iter()
+                            .filter(|rom_symbol| rom_symbol.bank == bank)
+                            .map(|rom_symbol| rom_symbol.size)
+                            .sum::<u32>();
+                        serde_json::json!({
+                            "bank": bank,
+                            "size": bank_size,
+                            "used": used,
+                            "symbols": symbols,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                serde_json::json!({
+                    "cfg": entry.key(),
+                    "rom": rom_info.path,
+                    "banks": banks,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(serde_json::Value::Array(projects))
+    }
+
+    fn get_relative_path(&self, path: &Path) -> Option<PathBuf> {
+        self.workspace_dirs
+            .iter()
+            .find_map(|file_path| path.strip_prefix(file_path.to_owned()).ok())
+            .map(|path| path.to_path_buf())
+    }
+
+    fn is_in_asm_function(&self, file_path: &Path, point: &Point) -> bool {
+        self.tree_map
+            .get(file_path)
+            .and_then(|tree| {
+                tree.root_node()
+                    .descendant_for_point_range(*point, *point)
+                    .and_then(|node| find_ancestor(node, "asm_function_definition"))
+                    .map(|_| ())
+            })
+            .is_some()
+    }
+
+    fn find_instruction(
+        &self,
+        file_path: &Path,
+        point: &Point,
+    ) -> anyhow::Result<Option<&'static Instruction>> {
+        if !self.is_in_asm_function(file_path, point) {
+            return Ok(None);
+        }
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let line = source.lines().nth(point.row).unwrap_or("");
+        // mnemonics are always the first word of an asm line
+        let mnemonic = line.split_whitespace().next().unwrap_or("");
+        let start = line.len() - line.trim_start().len();
+        if point.column < start || point.column > start + mnemonic.len() {
+            return Ok(None);
+        }
+        Ok(find_instruction(mnemonic))
+    }
+
+    // label name under the cursor, together with the lines of its asm function
+    fn find_label(
+        &self,
+        file_path: &Path,
+        point: &Point,
+    ) -> anyhow::Result<Option<(String, Vec<AsmLine>)>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let Some(node) = tree
+            .root_node()
+            .descendant_for_point_range(*point, *point)
+            .and_then(|node| find_ancestor(node, "asm_function_definition"))
+        else {
+            return Ok(None);
+        };
+        let lines = parse_asm_function(&source, &node);
+        let position = Position::new(point.row as u32, point.column as u32);
+        let name = lines
+            .iter()
+            .flat_map(|line| line.label.iter().chain(line.target()))
+            .find(|token| token.range.start <= position && position <= token.range.end)
+            .map(|token| token.text.clone());
+        let name = name.filter(|name| {
+            lines
+                .iter()
+                .filter_map(|line| line.label.as_ref())
+                .any(|label| &label.text == name)
+        });
+        Ok(name.map(|name| (name, lines)))
+    }
+
+    fn find_symbol(
+        &self,
+        file_path: &Path,
+        point: &Point,
+    ) -> anyhow::Result<Option<(PathBuf, Box<dyn Symbol>)>> {
+        let source = self
+            .source_map
+            .get(file_path)
+            .context(format!("failed to get source file: {file_path:?}"))?;
+        let tree = self
+            .tree_map
+            .get(file_path)
+            .context(format!("failed to get tree file: {file_path:?}"))?;
+        let node = tree
+            .root_node()
+            .descendant_for_point_range(*point, *point)
+            .context(format!("failed to get node file: {file_path:?}"))?;
+
+        if node.kind() == "identifier" {
+            let name = node.utf8_text(source.as_bytes())?;
+            let pair = self
+                .symbol_map
+                .get(file_path)
+                .and_then(|symbols| {
+                    symbols
+                        .find_symbol(&node, &name)
+                        .map(|symbol| (file_path.to_owned(), symbol))
+                        .ok()
+                })
+                .or(self
+                    .symbol_map
+                    .iter()
+                    .filter(|entry| entry.key() != &file_path)
+                    .find_map(|entry| {
+                        let (path, symbols) = entry.pair();
+                        symbols
+                            .find_symbol(&node, &name)
+                            .map(|symbol| (path.to_owned(), symbol))
+                            .ok()
+                    }));
+            return Ok(pair);
+        } else {
             return Ok(None);
         }
     }
@@ -287,6 +2872,15 @@ impl LanguageServer for Backend {
         for workspace_dir in workspace_dirs.into_iter() {
             self.workspace_dirs.insert(workspace_dir);
         }
+        let _ = self.client_capabilities.set(params.capabilities);
+        if let Some(options) = params.initialization_options.as_ref() {
+            if let Ok(mut settings) = self.compiler_settings.write() {
+                settings.update(options);
+            }
+            if let Ok(mut settings) = self.format_settings.write() {
+                settings.update(options);
+            }
+        }
 
         Ok(InitializeResult {
             server_info: None,
@@ -302,8 +2896,41 @@ impl LanguageServer for Backend {
                     file_operations: None,
                 }),
                 definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: "\n".to_string(),
+                    more_trigger_character: Some(vec!["e".to_string()]),
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                color_provider: Some(ColorProviderCapability::Simple(true)),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        "nesfab.build".to_string(),
+                        "nesfab.buildProject".to_string(),
+                    ],
+                    work_done_progress_options: WorkDoneProgressOptions {
+                        work_done_progress: Some(true),
+                    },
+                }),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                }),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
-                completion_provider: Some(CompletionOptions::default()),
+                completion_provider: Some(CompletionOptions {
+                    resolve_provider: Some(true),
+                    ..Default::default()
+                }),
                 ..Default::default()
             },
         })
@@ -336,6 +2963,7 @@ impl LanguageServer for Backend {
             },
         })
         .await;
+        self.register_file_watcher().await;
     }
 
     async fn shutdown(&self) -> jsonrpc::Result<()> {
@@ -372,18 +3000,30 @@ impl LanguageServer for Backend {
                 .await;
         }
     }
-    async fn did_save(&self, _: DidSaveTextDocumentParams) {
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
         self.client.log_message(MessageType::INFO, "did save").await;
+
+        if let Err(e) = self.on_save(params).await {
+            self.client
+                .log_message(MessageType::ERROR, format!("compiler error: {e:?}"))
+                .await;
+        }
     }
     async fn did_close(&self, _: DidCloseTextDocumentParams) {
         self.client
             .log_message(MessageType::INFO, "did close")
             .await;
     }
-    async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
         self.client
             .log_message(MessageType::INFO, "did change configuration")
             .await;
+        if let Ok(mut settings) = self.compiler_settings.write() {
+            settings.update(&params.settings);
+        }
+        if let Ok(mut settings) = self.format_settings.write() {
+            settings.update(&params.settings);
+        }
         let a = self.client.workspace_folders().await;
         self.client
             .log_message(MessageType::INFO, format!("nannkakita: {:?}", a))
@@ -403,10 +3043,25 @@ impl LanguageServer for Backend {
                 .await;
         }
     }
-    async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
         self.client
             .log_message(MessageType::INFO, "did change watched files")
             .await;
+        self.on_change_watched_files(params).await;
+    }
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> jsonrpc::Result<Option<CodeActionResponse>> {
+        match self.code_action(&params).await {
+            Ok(actions) => Ok(Some(actions)),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("code action error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
     }
 
     async fn completion(
@@ -427,6 +3082,20 @@ impl LanguageServer for Backend {
             }
         }
     }
+    async fn completion_resolve(&self, item: CompletionItem) -> jsonrpc::Result<CompletionItem> {
+        match self.completion_resolve(item) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("completion resolve error: {e:?}"),
+                    )
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
     async fn hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
         let (file_path, point) = file_path_and_point_from_params(
             &params.text_document_position_params.text_document,
@@ -460,6 +3129,605 @@ impl LanguageServer for Backend {
             }
         }
     }
+    async fn references(&self, params: ReferenceParams) -> jsonrpc::Result<Option<Vec<Location>>> {
+        let (file_path, point) = file_path_and_point_from_params(
+            &params.text_document_position.text_document,
+            &params.text_document_position.position,
+        )?;
+        match self.references(&file_path, &point, params.context.include_declaration) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("references error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> jsonrpc::Result<Option<Vec<CallHierarchyItem>>> {
+        let (file_path, point) = file_path_and_point_from_params(
+            &params.text_document_position_params.text_document,
+            &params.text_document_position_params.position,
+        )?;
+        match self.prepare_call_hierarchy(&file_path, &point) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("call hierarchy error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> jsonrpc::Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        match self.incoming_calls(&params.item) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("incoming calls error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> jsonrpc::Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        match self.outgoing_calls(&params.item) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("outgoing calls error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> jsonrpc::Result<Option<Vec<FoldingRange>>> {
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        match self.folding_range(&file_path) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("folding range error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn formatting(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        match self.formatting(&file_path, None).await {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("formatting error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        let rows = (params.range.start.line, params.range.end.line);
+        match self.formatting(&file_path, Some(rows)).await {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("formatting error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        let file_path = params
+            .text_document_position
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        let position = params.text_document_position.position;
+        match self.on_type_formatting(&file_path, position, &params.ch) {
+            Ok(edits) => Ok(edits),
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("on_type_formatting error: {e:?}"),
+                    )
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> jsonrpc::Result<Option<Vec<SelectionRange>>> {
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        match self.selection_range(&file_path, &params.positions) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("selection range error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> jsonrpc::Result<Option<Vec<DocumentHighlight>>> {
+        let file_path = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        let position = params.text_document_position_params.position;
+        match self.document_highlight(&file_path, position) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("document highlight error: {e:?}"),
+                    )
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> jsonrpc::Result<Option<DocumentSymbolResponse>> {
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        match self.document_symbol(&file_path) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("document symbol error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn document_link(
+        &self,
+        params: DocumentLinkParams,
+    ) -> jsonrpc::Result<Option<Vec<DocumentLink>>> {
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        match self.document_link(&file_path) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("document link error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> jsonrpc::Result<Option<serde_json::Value>> {
+        match self.execute_command(params).await {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("execute command error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn document_color(
+        &self,
+        params: DocumentColorParams,
+    ) -> jsonrpc::Result<Vec<ColorInformation>> {
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        match self.document_color(&file_path) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("document color error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+    async fn color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> jsonrpc::Result<Vec<ColorPresentation>> {
+        let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let color = params.color;
+        let index = nearest_index([
+            to_byte(color.red),
+            to_byte(color.green),
+            to_byte(color.blue),
+        ]);
+        let label = format!("${index:02X}");
+        Ok(vec![ColorPresentation {
+            text_edit: Some(TextEdit::new(params.range, label.clone())),
+            label,
+            additional_text_edits: None,
+        }])
+    }
+    async fn code_lens(&self, params: CodeLensParams) -> jsonrpc::Result<Option<Vec<CodeLens>>> {
+        let file_path = params
+            .text_document
+            .uri
+            .to_file_path()
+            .map_err(|_e| jsonrpc::Error::invalid_request())?;
+        match self.code_lens(&file_path) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("code lens error: {e:?}"))
+                    .await;
+                Err(jsonrpc::Error::internal_error())
+            }
+        }
+    }
+}
+
+fn find_ancestor<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+    let mut pivot = Some(node);
+    while let Some(node) = pivot {
+        if node.kind() == kind {
+            return Some(node);
+        }
+        pivot = node.parent();
+    }
+    None
+}
+
+// definitions named like palettes or documented as one
+fn collect_palette_nodes<'a>(source: &str, node: Node<'a>) -> Vec<Node<'a>> {
+    let bytes = source.as_bytes();
+    let is_palette_name = node
+        .child_by_field_name("name")
+        .and_then(|name| name.utf8_text(bytes).ok())
+        .is_some_and(|name| {
+            name.to_lowercase()
+                .split('_')
+                .any(|part| part == "pal" || part.starts_with("palette"))
+        });
+    let is_palette_comment = node
+        .prev_sibling()
+        .map(collect_sibling_comment_nodes)
+        .unwrap_or_default()
+        .iter()
+        .any(|comment| {
+            comment
+                .utf8_text(bytes)
+                .is_ok_and(|text| text.to_lowercase().contains("palette"))
+        });
+    match node.kind() {
+        "function_definition" | "asm_function_definition" => Vec::new(),
+        "comment" => Vec::new(),
+        _ if is_palette_name || is_palette_comment => vec![node],
+        _ => {
+            let mut cursor = node.walk();
+            node.children(&mut cursor)
+                .flat_map(|child| collect_palette_nodes(source, child))
+                .collect()
+        }
+    }
+}
+
+// symbols and usage of a file that isn't open, parsed once for both
+fn index_file(file_path: &Path) -> Option<(SymbolTable, FileUsage)> {
+    let source = std::fs::read_to_string(file_path).ok()?;
+    let mut parser = Parser::new();
+    parser.set_language(&tree_sitter_nesfab::language()).ok()?;
+    let tree = parser.parse(&source, None)?;
+    let mut symbol_table = SymbolTable::default();
+    traverse_tree(&source, &mut tree.root_node().walk(), &mut symbol_table).ok()?;
+    let usage = FileUsage::from_tree(&source, &tree);
+    Some((symbol_table, usage))
+}
+
+// the name inside a definition's range, or the whole range when it isn't found.
+// tokens count UTF-16 columns and symbol ranges bytes, so only rows are compared
+fn definition_name_range(tokens: &[IdentifierToken], name: &str, range: &Range) -> Range {
+    tokens
+        .iter()
+        .find(|token| {
+            token.text == name
+                && range.start.line <= token.range.start.line
+                && token.range.start.line <= range.end.line
+        })
+        .map(|token| token.range)
+        .unwrap_or(*range)
+}
+
+fn node_range(node: &Node) -> Range {
+    let start = node.start_position();
+    let end = node.end_position();
+    Range {
+        start: Position::new(start.row as u32, start.column as u32),
+        end: Position::new(end.row as u32, end.column as u32),
+    }
+}
+
+// tree-sitter point of an LSP position, whose column counts UTF-16 code units
+fn point_at(source: &str, position: Position) -> Point {
+    let line = source
+        .lines()
+        .nth(position.line as usize)
+        .unwrap_or_default();
+    let column = byte_offset(line, position.character).unwrap_or(line.len());
+    Point::new(position.line as usize, column)
+}
+
+// top-level comma separated arguments of `(a, f(b, c), d)`
+fn call_arguments(text: &str) -> Vec<String> {
+    let Some(start) = text.find('(') else {
+        return Vec::new();
+    };
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    let mut argument = String::new();
+    for c in text[start + 1..].chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' if depth == 0 => break,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                arguments.push(argument.trim().to_string());
+                argument.clear();
+                continue;
+            }
+            _ => {}
+        }
+        argument.push(c);
+    }
+    if !argument.trim().is_empty() || !arguments.is_empty() {
+        arguments.push(argument.trim().to_string());
+    }
+    arguments
+}
+
+// array literals, initializers and other nodes wrapped in a pair of bracket tokens
+fn collect_bracketed_nodes(node: Node) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let is_bracketed = match (
+        node.child(0),
+        node.child(node.child_count().saturating_sub(1)),
+    ) {
+        (Some(open), Some(close)) if open.id() != close.id() => matches!(
+            (open.kind(), close.kind()),
+            ("(", ")") | ("[", "]") | ("{", "}")
+        ),
+        _ => false,
+    };
+    if is_bracketed {
+        nodes.push(node);
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        nodes.extend(collect_bracketed_nodes(child));
+    }
+    nodes
+}
+
+// name ranges of the parameters and local variables of `function` called `name`
+fn local_definitions(source: &str, function: &Node, name: &str) -> Vec<Range> {
+    let bytes = source.as_bytes();
+    let is_name = |node: &Node| node.utf8_text(bytes).is_ok_and(|text| text == name);
+    let mut definitions = collect_nodes(*function, "variable_definition")
+        .into_iter()
+        .filter_map(|variable| variable.child_by_field_name("name"))
+        .filter(is_name)
+        .map(|node| utf16_range(source, &node))
+        .collect::<Vec<_>>();
+    // parameters are the last word of each comma separated item in the signature
+    if let Some(signature) = function.child_by_field_name("signature") {
+        let text = signature.utf8_text(bytes).unwrap_or_default();
+        let parameters = text
+            .split_once('(')
+            .and_then(|(_, rest)| rest.rsplit_once(')'))
+            .map(|(parameters, _)| parameters)
+            .unwrap_or_default();
+        let is_parameter = parameters
+            .split(',')
+            .any(|parameter| parameter.split_whitespace().last() == Some(name));
+        if is_parameter {
+            let range = collect_nodes(signature, "identifier")
+                .into_iter()
+                .filter(is_name)
+                .map(|node| utf16_range(source, &node))
+                .next_back();
+            definitions.extend(range);
+        }
+    }
+    definitions
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous + usize::from(a != *b);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn collect_nodes<'a>(node: Node<'a>, kind: &str) -> Vec<Node<'a>> {
+    let mut nodes = Vec::new();
+    let mut cursor = node.walk();
+    if node.kind() == kind {
+        nodes.push(node);
+    }
+    for child in node.children(&mut cursor) {
+        nodes.extend(collect_nodes(child, kind));
+    }
+    nodes
+}
+
+fn keyword_completion_items(source: &str, tree: &Tree, point: &Point) -> Vec<CompletionItem> {
+    let context = code_context(source, tree, point.row, point.column);
+    KEYWORDS
+        .iter()
+        .filter(|keyword| keyword.is_allowed(&context))
+        .map(|keyword| {
+            let documentation =
+                keyword
+                    .label
+                    .split(' ')
+                    .next()
+                    .and_then(find_builtin)
+                    .map(|builtin| {
+                        Documentation::MarkupContent(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value: builtin.markdown(),
+                        })
+                    });
+            CompletionItem {
+                label: keyword.label.to_string(),
+                kind: Some(if keyword.is_snippet {
+                    CompletionItemKind::SNIPPET
+                } else {
+                    CompletionItemKind::KEYWORD
+                }),
+                detail: Some(keyword.detail.to_string()),
+                documentation,
+                filter_text: keyword.is_snippet.then(|| {
+                    keyword
+                        .label
+                        .split(' ')
+                        .next()
+                        .unwrap_or_default()
+                        .to_string()
+                }),
+                insert_text: Some(keyword.insert_text.to_string()),
+                insert_text_format: keyword.is_snippet.then_some(InsertTextFormat::SNIPPET),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+// keywords are offered by keyword_completion_items where the context allows them
+fn builtin_completion_items() -> Vec<CompletionItem> {
+    BUILTINS
+        .iter()
+        .filter(|builtin| builtin.kind != BuiltinKind::Keyword)
+        .map(|builtin| CompletionItem {
+            label: builtin.name.to_string(),
+            kind: Some(match builtin.kind {
+                BuiltinKind::Type => CompletionItemKind::STRUCT,
+                BuiltinKind::Function => CompletionItemKind::FUNCTION,
+                BuiltinKind::Variable => CompletionItemKind::VARIABLE,
+                BuiltinKind::Keyword => CompletionItemKind::KEYWORD,
+            }),
+            detail: Some(builtin.signature.to_string()),
+            data: Some(serde_json::json!({ "kind": "builtin", "name": builtin.name })),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn asm_completion_items() -> Vec<CompletionItem> {
+    INSTRUCTIONS
+        .iter()
+        .flat_map(|instruction| {
+            let data = serde_json::json!({ "kind": "instruction", "name": instruction.mnemonic });
+            let mnemonic = CompletionItem {
+                label: instruction.mnemonic.to_lowercase(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                detail: Some(instruction.description.to_string()),
+                data: Some(data.clone()),
+                ..Default::default()
+            };
+            let modes = instruction
+                .encodings
+                .iter()
+                .filter(|encoding| encoding.mode != AddressingMode::Implied)
+                .map(move |encoding| CompletionItem {
+                    label: format!(
+                        "{} {}",
+                        instruction.mnemonic.to_lowercase(),
+                        encoding.mode.syntax()
+                    ),
+                    kind: Some(CompletionItemKind::SNIPPET),
+                    detail: Some(format!(
+                        "{}, {} bytes, {} cycles",
+                        encoding.mode.name(),
+                        encoding.mode.bytes(),
+                        encoding.cycles
+                    )),
+                    data: Some(data.clone()),
+                    filter_text: Some(instruction.mnemonic.to_lowercase()),
+                    insert_text: Some(format!(
+                        "{} {}",
+                        instruction.mnemonic.to_lowercase(),
+                        encoding.mode.snippet()
+                    )),
+                    insert_text_format: Some(InsertTextFormat::SNIPPET),
+                    ..Default::default()
+                });
+            std::iter::once(mnemonic).chain(modes)
+        })
+        .collect()
 }
 
 fn file_path_and_point_from_params(
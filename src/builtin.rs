@@ -0,0 +1,289 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinKind {
+    Type,
+    Function,
+    Variable,
+    Keyword,
+}
+
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub kind: BuiltinKind,
+    pub signature: &'static str,
+    pub documentation: &'static str,
+}
+
+impl Builtin {
+    pub fn markdown(&self) -> String {
+        format!("```nesfab\n{}\n```\n{}", self.signature, self.documentation)
+    }
+}
+
+pub fn find_builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+const fn builtin(
+    name: &'static str,
+    kind: BuiltinKind,
+    signature: &'static str,
+    documentation: &'static str,
+) -> Builtin {
+    Builtin {
+        name,
+        kind,
+        signature,
+        documentation,
+    }
+}
+
+use BuiltinKind::*;
+
+pub static BUILTINS: &[Builtin] = &[
+    // types
+    builtin("U", Type, "U", "Unsigned 1-byte integer (0 to 255)."),
+    builtin("UU", Type, "UU", "Unsigned 2-byte integer (0 to 65535)."),
+    builtin("UUU", Type, "UUU", "Unsigned 3-byte integer."),
+    builtin("S", Type, "S", "Signed 1-byte integer (-128 to 127)."),
+    builtin("SS", Type, "SS", "Signed 2-byte integer (-32768 to 32767)."),
+    builtin("SSS", Type, "SSS", "Signed 3-byte integer."),
+    builtin(
+        "F",
+        Type,
+        "F",
+        "Fractional byte, appended to `U`/`S` to form fixed-point types such as `UF` or `SSFF`.",
+    ),
+    builtin(
+        "UF",
+        Type,
+        "UF",
+        "Unsigned fixed-point: 1 whole byte, 1 fractional byte.",
+    ),
+    builtin(
+        "UUF",
+        Type,
+        "UUF",
+        "Unsigned fixed-point: 2 whole bytes, 1 fractional byte.",
+    ),
+    builtin(
+        "UFF",
+        Type,
+        "UFF",
+        "Unsigned fixed-point: 1 whole byte, 2 fractional bytes.",
+    ),
+    builtin(
+        "SF",
+        Type,
+        "SF",
+        "Signed fixed-point: 1 whole byte, 1 fractional byte.",
+    ),
+    builtin(
+        "SSF",
+        Type,
+        "SSF",
+        "Signed fixed-point: 2 whole bytes, 1 fractional byte.",
+    ),
+    builtin("Bool", Type, "Bool", "Boolean value: `true` or `false`."),
+    builtin(
+        "Int",
+        Type,
+        "Int",
+        "Arbitrary-size integer, only usable at compile time.",
+    ),
+    builtin(
+        "Real",
+        Type,
+        "Real",
+        "Real number, only usable at compile time.",
+    ),
+    builtin(
+        "Void",
+        Type,
+        "Void",
+        "No value. Used as the return type of functions returning nothing.",
+    ),
+    builtin("AA", Type, "AA", "Raw 2-byte address."),
+    builtin(
+        "PP",
+        Type,
+        "PP",
+        "2-byte pointer into RAM, typed by the groups it may point to.",
+    ),
+    builtin(
+        "PPP",
+        Type,
+        "PPP",
+        "3-byte banked pointer into RAM or ROM data.",
+    ),
+    builtin("CC", Type, "CC", "2-byte pointer into constant data."),
+    builtin(
+        "CCC",
+        Type,
+        "CCC",
+        "3-byte banked pointer into constant data.",
+    ),
+    // intrinsics and standard library
+    builtin(
+        "len",
+        Function,
+        "fn len(array) Int",
+        "Number of elements in an array, known at compile time.",
+    ),
+    builtin(
+        "abs",
+        Function,
+        "fn abs(value) T",
+        "Absolute value of a signed number.",
+    ),
+    builtin("min", Function, "fn min(a, b) T", "Smaller of two values."),
+    builtin("max", Function, "fn max(a, b) T", "Larger of two values."),
+    builtin(
+        "nmi_counter",
+        Variable,
+        "U nmi_counter",
+        "Incremented by the standard library NMI handler once per frame.",
+    ),
+    builtin(
+        "wait",
+        Function,
+        "fn wait(U frames)",
+        "Standard library: busy-waits for the given number of frames using `nmi_counter`.",
+    ),
+    builtin(
+        "ppu_upload",
+        Function,
+        "fn ppu_upload(CCC data)",
+        "Standard library: uploads data to the PPU. Call with rendering off or during vblank.",
+    ),
+    // keywords
+    builtin(
+        "fn",
+        Keyword,
+        "fn name(Type arg) ReturnType",
+        "Defines a function.",
+    ),
+    builtin(
+        "asm",
+        Keyword,
+        "asm fn name()",
+        "Defines a function written in 6502 assembly.",
+    ),
+    builtin(
+        "mode",
+        Keyword,
+        "mode name()",
+        "Defines a mode: a function entered with `goto mode` that never returns.",
+    ),
+    builtin(
+        "nmi",
+        Keyword,
+        "nmi name()",
+        "Defines an NMI handler, run each vblank while its mode is active.",
+    ),
+    builtin("irq", Keyword, "irq name()", "Defines an IRQ handler."),
+    builtin(
+        "vars",
+        Keyword,
+        "vars /group",
+        "Declares a group of global variables.",
+    ),
+    builtin(
+        "data",
+        Keyword,
+        "data /group",
+        "Declares a group of constant data stored in ROM.",
+    ),
+    builtin(
+        "omni",
+        Keyword,
+        "omni /group",
+        "Declares data that is present in every bank.",
+    ),
+    builtin(
+        "ct",
+        Keyword,
+        "ct Type name = value",
+        "Declares a compile-time constant.",
+    ),
+    builtin("struct", Keyword, "struct Name", "Defines a struct type."),
+    builtin(
+        "charmap",
+        Keyword,
+        "charmap name(\"characters\", 'sentinel')",
+        "Defines the character encoding used by string literals.",
+    ),
+    builtin(
+        "file",
+        Keyword,
+        "file(format, \"path\")",
+        "Includes the contents of a resource file.",
+    ),
+    builtin(
+        "goto",
+        Keyword,
+        "goto mode name()",
+        "Transfers control to a mode. Execution never returns.",
+    ),
+    builtin(
+        "fence",
+        Keyword,
+        "fence",
+        "Waits until the next NMI has run.",
+    ),
+    builtin(
+        "return",
+        Keyword,
+        "return value",
+        "Returns from the current function.",
+    ),
+    builtin(
+        "if",
+        Keyword,
+        "if condition",
+        "Executes the indented block when the condition is true.",
+    ),
+    builtin(
+        "else",
+        Keyword,
+        "else",
+        "Executes the indented block when the preceding `if` was false.",
+    ),
+    builtin(
+        "for",
+        Keyword,
+        "for init; condition; step",
+        "Loops while the condition is true.",
+    ),
+    builtin(
+        "while",
+        Keyword,
+        "while condition",
+        "Loops while the condition is true.",
+    ),
+    builtin(
+        "do",
+        Keyword,
+        "do ... while condition",
+        "Loops at least once, then while the condition is true.",
+    ),
+    builtin(
+        "switch",
+        Keyword,
+        "switch value",
+        "Branches to the `case` matching the value.",
+    ),
+    builtin("case", Keyword, "case value", "A branch of a `switch`."),
+    builtin(
+        "break",
+        Keyword,
+        "break",
+        "Exits the innermost loop or switch.",
+    ),
+    builtin(
+        "continue",
+        Keyword,
+        "continue",
+        "Jumps to the next iteration of the innermost loop.",
+    ),
+];
@@ -6,11 +6,19 @@ use std::{
 };
 
 use rayon::prelude::*;
+use tower_lsp::lsp_types::Position;
 use walkdir::WalkDir;
 
+#[derive(Debug, Clone, Default)]
+pub struct CfgProject {
+    pub cfg_file_path: PathBuf,
+    pub inputs: HashSet<PathBuf>,
+}
+
+// projects keyed by the directory of their .cfg file
 pub fn collect_cfg_map<T: AsRef<Path>>(
     files: &[T],
-) -> anyhow::Result<HashMap<PathBuf, HashSet<PathBuf>>> {
+) -> anyhow::Result<HashMap<PathBuf, CfgProject>> {
     let cfg_file_paths = files
         .iter()
         .flat_map(|path| {
@@ -34,30 +42,34 @@ pub fn collect_cfg_map<T: AsRef<Path>>(
             if let Some(cfg_dir) = cfg_file_path.parent() {
                 extract_inputs(&cfg_file_path)
                     .ok()
-                    .map(|paths| (cfg_dir, paths))
+                    .map(|paths| (cfg_file_path, cfg_dir, paths))
             } else {
                 None
             }
         })
-        .map(|(cfg_dir, paths)| {
+        .map(|(cfg_file_path, cfg_dir, paths)| {
+            let inputs = paths
+                .into_iter()
+                .filter_map(|path| {
+                    if let Ok(file_path) = fs::canonicalize(cfg_dir.join(&path)) {
+                        return Some(file_path);
+                    }
+                    if let Ok(file_path) = fs::canonicalize(nesfab_path.join(&path)) {
+                        return Some(file_path);
+                    }
+                    None
+                })
+                .filter(|path| match path.extension() {
+                    Some(extension) => extension == "fab", // remove macrofab
+                    None => false,
+                })
+                .collect::<HashSet<_>>();
             (
                 cfg_dir.to_path_buf(),
-                paths
-                    .into_iter()
-                    .filter_map(|path| {
-                        if let Ok(file_path) = fs::canonicalize(cfg_dir.join(&path)) {
-                            return Some(file_path);
-                        }
-                        if let Ok(file_path) = fs::canonicalize(nesfab_path.join(&path)) {
-                            return Some(file_path);
-                        }
-                        None
-                    })
-                    .filter(|path| match path.extension() {
-                        Some(extension) => extension == "fab", // remove macrofab
-                        None => false,
-                    })
-                    .collect::<HashSet<_>>(),
+                CfgProject {
+                    cfg_file_path: cfg_file_path.to_path_buf(),
+                    inputs,
+                },
             )
         })
         .collect::<HashMap<_, _>>();
@@ -65,16 +77,92 @@ pub fn collect_cfg_map<T: AsRef<Path>>(
     Ok(cfg_map)
 }
 
+pub fn collect_resource_dirs<T: AsRef<Path>>(cfg_file_path: &T) -> anyhow::Result<Vec<PathBuf>> {
+    let cfg_dir = cfg_file_path
+        .as_ref()
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let resource_dirs = extract_option(cfg_file_path, "resource-dir")?
+        .into_iter()
+        .map(|path| cfg_dir.join(path))
+        .collect();
+    Ok(resource_dirs)
+}
+
+pub fn collect_output_path<T: AsRef<Path>>(cfg_file_path: &T) -> Option<PathBuf> {
+    let cfg_dir = cfg_file_path.as_ref().parent()?;
+    extract_option(cfg_file_path, "output")
+        .ok()?
+        .into_iter()
+        .next()
+        .map(|path| cfg_dir.join(path))
+}
+
+// path of `file_path` relative to `dir`, climbing out with `..` where needed
+pub fn relative_path(dir: &Path, file_path: &Path) -> PathBuf {
+    let dir_components = dir.components().collect::<Vec<_>>();
+    let file_components = file_path.components().collect::<Vec<_>>();
+    let common = dir_components
+        .iter()
+        .zip(file_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut path = PathBuf::new();
+    for _ in common..dir_components.len() {
+        path.push("..");
+    }
+    for component in &file_components[common..] {
+        path.push(component);
+    }
+    path
+}
+
+// position and text that add `file_path` as an input, placed after the last existing input
+pub fn input_insertion<T: AsRef<Path>>(
+    cfg_file_path: &T,
+    file_path: &Path,
+) -> anyhow::Result<(Position, String)> {
+    let content = fs::read_to_string(cfg_file_path)?;
+    let cfg_dir = cfg_file_path.as_ref().parent().unwrap_or(Path::new(""));
+    let relative_path = relative_path(cfg_dir, file_path);
+    let line = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            line.split('=')
+                .next()
+                .is_some_and(|name| name.trim() == "input")
+        })
+        .map(|(row, _)| row + 1)
+        .last();
+    let text = format!("input = {}\n", relative_path.to_string_lossy());
+    let line_count = content.lines().count() as u32;
+    let line = line.map_or(line_count, |line| line as u32);
+    match content.lines().last() {
+        // appending after a last line that has no newline of its own
+        Some(last) if line == line_count && !content.ends_with('\n') => Ok((
+            Position::new(line_count - 1, last.len() as u32),
+            format!("\n{text}"),
+        )),
+        _ => Ok((Position::new(line, 0), text)),
+    }
+}
+
 fn extract_inputs<T: AsRef<Path>>(path: &T) -> anyhow::Result<Vec<PathBuf>> {
+    extract_option(path, "input")
+}
+
+fn extract_option<T: AsRef<Path>>(path: &T, name: &str) -> anyhow::Result<Vec<PathBuf>> {
     let file = std::fs::File::open(path)?;
     let reader = std::io::BufReader::new(file);
     let paths = reader
         .lines()
         .filter_map(|line| line.ok())
         .filter_map(|line| {
-            if line.starts_with("input") {
+            if line.starts_with(name) {
                 let parts = line.split('=').map(str::trim).collect::<Vec<_>>();
-                if parts.len() == 2 {
+                if parts.len() == 2 && parts[0] == name {
                     let path = Path::new(parts[1]).to_path_buf();
                     return Some(path);
                 }
@@ -0,0 +1,145 @@
+use crate::utf16::{byte_offset, utf16_len};
+use tower_lsp::lsp_types::{Position, Range};
+
+#[derive(Debug, Clone)]
+pub struct StringLiteral {
+    // unescaped characters with the source range each one came from
+    pub characters: Vec<(char, Range)>,
+    pub range: Range,
+    pub charmap: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CharmapDefinition {
+    pub name: String,
+    pub name_range: Range,
+    pub characters: Vec<char>,
+    pub sentinel: Option<char>,
+    pub is_default: bool,
+}
+
+// LSP range of the byte span `start..end` of `line`
+fn range(row: usize, line: &str, start: usize, end: usize) -> Range {
+    Range {
+        start: Position::new(row as u32, utf16_len(&line[..start])),
+        end: Position::new(row as u32, utf16_len(&line[..end])),
+    }
+}
+
+fn unescape(character: char) -> char {
+    match character {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        other => other,
+    }
+}
+
+// parses a quoted literal starting at `start`, returning its characters and the end offset
+fn parse_quoted(
+    row: usize,
+    line: &str,
+    start: usize,
+    quote: char,
+) -> Option<(Vec<(char, Range)>, usize)> {
+    let mut characters = Vec::new();
+    let mut chars = line[start + 1..].char_indices();
+    while let Some((offset, character)) = chars.next() {
+        let begin = start + 1 + offset;
+        if character == quote {
+            return Some((characters, begin + 1));
+        }
+        if character == '\\' {
+            let (offset, escaped) = chars.next()?;
+            let end = start + 1 + offset + escaped.len_utf8();
+            characters.push((unescape(escaped), range(row, line, begin, end)));
+        } else {
+            let end = begin + character.len_utf8();
+            characters.push((character, range(row, line, begin, end)));
+        }
+    }
+    None
+}
+
+fn strip_comment(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let mut quote = None;
+    let mut index = 0;
+    while index < bytes.len() {
+        match (quote, bytes[index]) {
+            // the escaped byte can't close the literal
+            (Some(_), b'\\') => index += 1,
+            (Some(q), byte) if byte == q => quote = None,
+            (None, byte @ (b'"' | b'\'')) => quote = Some(byte),
+            (None, b'/') if bytes.get(index + 1) == Some(&b'/') => return &line[..index],
+            _ => {}
+        }
+        index += 1;
+    }
+    line
+}
+
+pub fn collect_string_literals(row: usize, line: &str) -> Vec<StringLiteral> {
+    let code = strip_comment(line);
+    let mut literals = Vec::new();
+    let mut offset = 0;
+    while let Some(index) = code[offset..].find(['"', '\'']) {
+        let start = offset + index;
+        let quote = code[start..].chars().next().unwrap_or('"');
+        let Some((characters, end)) = parse_quoted(row, code, start, quote) else {
+            break;
+        };
+        offset = end;
+        if quote == '\'' {
+            continue;
+        }
+        let suffix = code[end..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect::<String>();
+        offset += suffix.len();
+        literals.push(StringLiteral {
+            characters,
+            range: range(row, code, start, offset),
+            charmap: Some(suffix).filter(|suffix| !suffix.is_empty()),
+        });
+    }
+    literals
+}
+
+// `charmap name("characters", 'sentinel') : +default`
+pub fn parse_charmap_definition(row: usize, line: &str) -> Option<CharmapDefinition> {
+    let code = strip_comment(line);
+    let rest = code.trim_start().strip_prefix("charmap")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let name_start = code.len() - rest.trim_start().len();
+    let name_end = name_start
+        + code[name_start..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .count();
+    if name_start == name_end {
+        return None;
+    }
+    let literal = collect_string_literals(row, code).into_iter().next()?;
+    let characters = literal
+        .characters
+        .iter()
+        .map(|(character, _)| *character)
+        .collect();
+    let literal_end = byte_offset(code, literal.range.end.character)?;
+    let sentinel = code[literal_end..].find('\'').and_then(|offset| {
+        parse_quoted(row, code, literal_end + offset, '\'')
+            .and_then(|(characters, _)| characters.first().map(|(character, _)| *character))
+    });
+    Some(CharmapDefinition {
+        name: code[name_start..name_end].to_string(),
+        name_range: range(row, code, name_start, name_end),
+        characters,
+        sentinel,
+        is_default: code.contains("+default"),
+    })
+}
@@ -0,0 +1,341 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Position, Range};
+
+#[derive(Debug, Clone)]
+pub struct CompilerSettings {
+    pub enabled: bool,
+    pub path: PathBuf,
+    pub debounce: Duration,
+}
+
+impl Default for CompilerSettings {
+    fn default() -> Self {
+        let path = match option_env!("NESFAB") {
+            Some(nesfab_dir) => Path::new(nesfab_dir).join("nesfab"),
+            None => PathBuf::from("nesfab"),
+        };
+        Self {
+            enabled: false,
+            path,
+            debounce: Duration::from_millis(500),
+        }
+    }
+}
+
+impl CompilerSettings {
+    // reads `{ "compiler": { "enabled", "path", "debounceMs" } }`, optionally nested in "nesfab"
+    pub fn update(&mut self, value: &serde_json::Value) {
+        let compiler = value
+            .get("compiler")
+            .or_else(|| value.get("nesfab").and_then(|value| value.get("compiler")));
+        if let Some(compiler) = compiler {
+            if let Some(enabled) = compiler.get("enabled").and_then(|value| value.as_bool()) {
+                self.enabled = enabled;
+            }
+            if let Some(path) = compiler.get("path").and_then(|value| value.as_str()) {
+                self.path = PathBuf::from(path);
+            }
+            if let Some(debounce) = compiler.get("debounceMs").and_then(|value| value.as_u64()) {
+                self.debounce = Duration::from_millis(debounce);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompilerMessage {
+    pub file_path: PathBuf,
+    pub range: Range,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+}
+
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip a CSI sequence such as `\x1b[1;31m`
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+fn parse_location_line(line: &str, base_dir: &Path) -> Option<CompilerMessage> {
+    let (location, severity, message) = [
+        (": error: ", DiagnosticSeverity::ERROR),
+        (": warning: ", DiagnosticSeverity::WARNING),
+        (": note: ", DiagnosticSeverity::INFORMATION),
+    ]
+    .into_iter()
+    .find_map(|(marker, severity)| {
+        line.split_once(marker)
+            .map(|(location, message)| (location.to_string(), severity, message))
+    })?;
+    let mut parts = location.rsplitn(3, ':');
+    let last = parts.next()?;
+    let second = parts.next()?;
+    let (path, row, column) = match (parts.next(), second.parse::<u32>(), last.parse::<u32>()) {
+        (Some(path), Ok(row), Ok(column)) => (path, row, column),
+        (_, _, Ok(row)) => (location.rsplit_once(':')?.0, row, 1),
+        _ => return None,
+    };
+    let position = Position::new(row.saturating_sub(1), column.saturating_sub(1));
+    let file_path = base_dir.join(path.trim());
+    Some(CompilerMessage {
+        file_path: std::fs::canonicalize(&file_path).unwrap_or(file_path),
+        range: Range {
+            start: position,
+            end: Position::new(position.line, position.character + 1),
+        },
+        severity,
+        message: message.trim().to_string(),
+    })
+}
+
+// parses `path:line[:column]: error|warning|note: message` lines. indented lines that follow
+// belong to the message, and a line of `^` marks the width of the range
+pub fn parse_output(output: &str, base_dir: &Path) -> Vec<CompilerMessage> {
+    let mut messages = Vec::<CompilerMessage>::new();
+    let mut is_continuation = false;
+    for line in output.lines().map(strip_ansi) {
+        if let Some(message) = parse_location_line(&line, base_dir) {
+            messages.push(message);
+            is_continuation = true;
+            continue;
+        }
+        let is_indented = line.starts_with(char::is_whitespace);
+        let (Some(message), true, true) = (messages.last_mut(), is_continuation, is_indented)
+        else {
+            is_continuation = false;
+            continue;
+        };
+        let content = line.trim();
+        if content.is_empty() {
+            is_continuation = false;
+        } else if content.chars().all(|c| c == '^' || c == '~') {
+            let width = content.chars().count() as u32;
+            message.range.end.character = message.range.start.character + width;
+        } else {
+            message.message.push('\n');
+            message.message.push_str(content);
+        }
+    }
+    messages
+}
+
+// the child process is killed when dropped
+pub fn spawn_compiler(
+    compiler_path: &Path,
+    cfg_file_path: &Path,
+) -> anyhow::Result<tokio::process::Child> {
+    let cfg_dir = cfg_file_path.parent().unwrap_or(Path::new("."));
+    let child = tokio::process::Command::new(compiler_path)
+        .arg(cfg_file_path)
+        .current_dir(cfg_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    Ok(child)
+}
+
+pub async fn run_compiler(
+    compiler_path: &Path,
+    cfg_file_path: &Path,
+) -> anyhow::Result<std::process::Output> {
+    let output = spawn_compiler(compiler_path, cfg_file_path)?
+        .wait_with_output()
+        .await?;
+    Ok(output)
+}
+
+#[derive(Debug, Clone)]
+pub struct RomInfo {
+    pub path: PathBuf,
+    pub size: u64,
+    // in 16 KiB units
+    pub prg_banks: u8,
+    // in 8 KiB units
+    pub chr_banks: u8,
+    pub mapper: u8,
+}
+
+impl RomInfo {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let header = bytes
+            .get(..16)
+            .filter(|header| header.starts_with(b"NES\x1a"))
+            .ok_or_else(|| anyhow::anyhow!("not an iNES file: {path:?}"))?;
+        Ok(RomInfo {
+            path: path.to_path_buf(),
+            size: bytes.len() as u64,
+            prg_banks: header[4],
+            chr_banks: header[5],
+            mapper: (header[6] >> 4) | (header[7] & 0xF0),
+        })
+    }
+
+    // switchable PRG bank size of common NESFab mappers
+    pub fn prg_bank_size(&self) -> u32 {
+        match self.mapper {
+            0 => self.prg_banks.max(1) as u32 * 0x4000,
+            1 | 2 | 30 => 0x4000,
+            4 => 0x2000,
+            _ => 0x8000,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let file_name = self
+            .path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        format!(
+            "{} ({} bytes): PRG {} x 16 KiB, CHR {} x 8 KiB, mapper {}",
+            file_name, self.size, self.prg_banks, self.chr_banks, self.mapper
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RomSymbol {
+    pub name: String,
+    // offset into PRG ROM
+    pub offset: u32,
+    pub size: u32,
+    pub bank: u32,
+}
+
+// reads PRG ROM labels from a Mesen label file (`P:offset:name` or `NesPrgRom:offset:name`)
+pub fn parse_mlb(content: &str, bank_size: u32) -> Vec<RomSymbol> {
+    let mut labels = content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().splitn(4, ':');
+            let memory_type = parts.next()?;
+            if memory_type != "P" && memory_type != "NesPrgRom" {
+                return None;
+            }
+            let address = parts.next()?;
+            let name = parts.next().filter(|name| !name.is_empty())?;
+            let (start, end) = match address.split_once('-') {
+                Some((start, end)) => (start, Some(end)),
+                None => (address, None),
+            };
+            let start = u32::from_str_radix(start, 16).ok()?;
+            let end = end.and_then(|end| u32::from_str_radix(end, 16).ok());
+            Some((name.to_string(), start, end))
+        })
+        .collect::<Vec<_>>();
+    labels.sort_by_key(|(_, start, _)| *start);
+    let bank_size = bank_size.max(1);
+    labels
+        .iter()
+        .enumerate()
+        .map(|(index, (name, start, end))| {
+            let bank_end = (start / bank_size + 1) * bank_size;
+            // the last label of a bank has no known end without an explicit range
+            let next = labels
+                .get(index + 1)
+                .map(|(_, next, _)| *next)
+                .filter(|next| *next < bank_end)
+                .unwrap_or(*start);
+            let size = match end {
+                Some(end) => end + 1 - start,
+                None => next - start,
+            };
+            RomSymbol {
+                name: name.clone(),
+                offset: *start,
+                size,
+                bank: start / bank_size,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_output_reads_location_and_severity() {
+        let output = "src/main.fab:3:5: error: unknown identifier foo\n\
+                      src/util.fab:10: warning: unused variable\n\
+                      \x1b[1;36msrc/main.fab:1:1: note: \x1b[0mdeclared here\n\
+                      linking...\n";
+        let messages = parse_output(output, Path::new("/project"));
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].file_path, Path::new("/project/src/main.fab"));
+        assert_eq!(messages[0].range.start, Position::new(2, 4));
+        assert_eq!(messages[0].severity, DiagnosticSeverity::ERROR);
+        assert_eq!(messages[0].message, "unknown identifier foo");
+        assert_eq!(messages[1].file_path, Path::new("/project/src/util.fab"));
+        assert_eq!(messages[1].range.start, Position::new(9, 0));
+        assert_eq!(messages[1].severity, DiagnosticSeverity::WARNING);
+        assert_eq!(messages[2].severity, DiagnosticSeverity::INFORMATION);
+        assert_eq!(messages[2].message, "declared here");
+    }
+
+    #[test]
+    fn parse_output_joins_continuation_lines() {
+        let output = "main.fab:2:9: error: type mismatch\n\
+                      \x20   expected U, found Bool\n\
+                      \x20   ^^^^\n\
+                      \n\
+                      \x20   not part of any message\n";
+        let messages = parse_output(output, Path::new("/project"));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message, "type mismatch\nexpected U, found Bool");
+        assert_eq!(messages[0].range.end, Position::new(1, 12));
+    }
+
+    #[test]
+    fn parse_output_resolves_paths_against_the_cfg_dir() {
+        let base_dir = std::env::temp_dir();
+        let messages = parse_output("../lib/a.fab:1:1: error: x", &base_dir);
+        assert_eq!(messages[0].file_path, base_dir.join("../lib/a.fab"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_compiler_runs_a_stand_in_script() -> anyhow::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("nesfab-ls-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let script_path = dir.join("nesfab");
+        std::fs::write(
+            &script_path,
+            "#!/bin/sh\necho \"main.fab:4:2: error: called with $1\" >&2\nexit 1\n",
+        )?;
+        std::fs::set_permissions(&script_path, std::fs::Permissions::from_mode(0o755))?;
+        let cfg_file_path = dir.join("game.cfg");
+        std::fs::write(&cfg_file_path, "input = main.fab\n")?;
+
+        let output = run_compiler(&script_path, &cfg_file_path).await?;
+        std::fs::remove_dir_all(&dir)?;
+        assert!(!output.status.success());
+        let messages = parse_output(&String::from_utf8_lossy(&output.stderr), &dir);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].file_path, dir.join("main.fab"));
+        assert_eq!(messages[0].range.start, Position::new(3, 1));
+        assert!(messages[0].message.ends_with("game.cfg"));
+        Ok(())
+    }
+}
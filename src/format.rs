@@ -0,0 +1,1074 @@
+use crate::utf16::utf16_len;
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+use tree_sitter::{Tree, TreeCursor};
+
+pub const INDENT_WIDTH: usize = 4;
+
+// longest first so that `<<=` wins over `<<` and `<`
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "==", "!=", "<=", ">=", "+=", "-=", "*=", "&=", "|=", "^=", "&&", "||", "<<",
+    ">>", "=", "<", ">", "+", "-", "*", "&", "|", "^",
+];
+
+// operators after these words are unary, as in `return -1`
+const KEYWORDS: &[&str] = &[
+    "return", "if", "else", "while", "for", "do", "switch", "case", "goto", "ct",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Operator(String),
+    Comma,
+    Open(char),
+    Close(char),
+    // string and character literals, copied verbatim
+    Literal(String),
+    Other(char),
+    Space,
+}
+
+fn tokenize(code: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let chars = code.char_indices().collect::<Vec<_>>();
+    let mut index = 0;
+    while index < chars.len() {
+        let (offset, c) = chars[index];
+        match c {
+            ' ' | '\t' => {
+                while index < chars.len() && matches!(chars[index].1, ' ' | '\t') {
+                    index += 1;
+                }
+                tokens.push(Token::Space);
+                continue;
+            }
+            '"' | '\'' => {
+                let start = offset;
+                index += 1;
+                while index < chars.len() && chars[index].1 != c {
+                    index += if chars[index].1 == '\\' { 2 } else { 1 };
+                }
+                index += 1;
+                let end = chars.get(index).map_or(code.len(), |(offset, _)| *offset);
+                tokens.push(Token::Literal(code[start..end].to_string()));
+                continue;
+            }
+            ',' => tokens.push(Token::Comma),
+            '(' | '[' | '{' => tokens.push(Token::Open(c)),
+            ')' | ']' | '}' => tokens.push(Token::Close(c)),
+            _ if c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '%' | '.') => {
+                let start = offset;
+                while index < chars.len()
+                    && (chars[index].1.is_ascii_alphanumeric()
+                        || matches!(chars[index].1, '_' | '$' | '%' | '.'))
+                {
+                    index += 1;
+                }
+                let end = chars.get(index).map_or(code.len(), |(offset, _)| *offset);
+                tokens.push(Token::Word(code[start..end].to_string()));
+                continue;
+            }
+            _ => {
+                let rest = &code[offset..];
+                // `++` and `--` keep their spacing
+                if rest.starts_with("++") || rest.starts_with("--") {
+                    tokens.push(Token::Other(c));
+                    tokens.push(Token::Other(c));
+                    index += 2;
+                    continue;
+                }
+                if let Some(operator) = OPERATORS
+                    .iter()
+                    .find(|operator| rest.starts_with(**operator))
+                {
+                    tokens.push(Token::Operator(operator.to_string()));
+                    index += operator.chars().count();
+                    continue;
+                }
+                tokens.push(Token::Other(c));
+            }
+        }
+        index += 1;
+    }
+    tokens
+}
+
+// spaces around binary operators, one space after commas and none inside brackets
+fn format_code(code: &str) -> String {
+    let is_operand = |token: &Token| match token {
+        Token::Word(word) => !KEYWORDS.contains(&word.as_str()),
+        Token::Literal(_) | Token::Close(_) => true,
+        _ => false,
+    };
+    let mut output = String::new();
+    let mut previous: Option<&Token> = None;
+    let mut pending_space = false;
+    // whether the last operator was unary, like the `-` of `x = -1`
+    let mut is_unary = false;
+    let tokens = tokenize(code);
+    for token in &tokens {
+        if *token == Token::Space {
+            pending_space = true;
+            continue;
+        }
+        let space_before = match (previous, token) {
+            (None, _) | (_, Token::Comma) | (_, Token::Close(_)) | (Some(Token::Open(_)), _) => {
+                false
+            }
+            (Some(Token::Comma), _) => true,
+            (Some(Token::Operator(_)), _) => !is_unary,
+            (Some(previous), Token::Operator(_)) => is_operand(previous) || pending_space,
+            _ => pending_space,
+        };
+        if let Token::Operator(_) = token {
+            is_unary = !previous.is_some_and(is_operand);
+        }
+        if space_before {
+            output.push(' ');
+        }
+        match token {
+            Token::Word(text) | Token::Operator(text) | Token::Literal(text) => {
+                output.push_str(text)
+            }
+            Token::Comma => output.push(','),
+            Token::Open(c) | Token::Close(c) | Token::Other(c) => output.push(*c),
+            Token::Space => {}
+        }
+        pending_space = false;
+        previous = Some(token);
+    }
+    output
+}
+
+// splits off a trailing `//` comment outside of literals
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let bytes = line.as_bytes();
+    let mut quote = None;
+    let mut index = 0;
+    while index < bytes.len() {
+        match (quote, bytes[index]) {
+            (Some(_), b'\\') => index += 1,
+            (Some(q), byte) if byte == q => quote = None,
+            (None, byte @ (b'"' | b'\'')) => quote = Some(byte),
+            (None, b'/') if bytes.get(index + 1) == Some(&b'/') => {
+                return (&line[..index], Some(&line[index..]));
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    (line, None)
+}
+
+// net bracket depth change of a line, ignoring literals and comments
+fn bracket_delta(code: &str) -> isize {
+    tokenize(code)
+        .iter()
+        .map(|token| match token {
+            Token::Open(_) => 1,
+            Token::Close(_) => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    level: usize,
+    // code without indentation and trailing comment
+    code: String,
+    comment: Option<String>,
+    // kept verbatim apart from indentation
+    is_verbatim: bool,
+    // inside a multi-line bracket or block comment, already indented
+    is_continuation: bool,
+}
+
+impl Line {
+    fn is_blank(&self) -> bool {
+        self.code.is_empty() && self.comment.is_none()
+    }
+}
+
+fn format_lines(source: &str) -> Vec<String> {
+    let mut lines = Vec::<Line>::new();
+    let mut widths = vec![0];
+    let mut depth = 0isize;
+    // original and formatted indentation of the line that opened the current brackets
+    let mut bracket_base = (0, 0);
+    let mut in_asm = false;
+    let mut in_block_comment = false;
+    for text in source.lines() {
+        let content = text.trim_start();
+        let width = text.len() - content.len();
+        let content = content.trim_end();
+        if content.is_empty() {
+            lines.push(Line {
+                level: 0,
+                code: String::new(),
+                comment: None,
+                is_verbatim: false,
+                is_continuation: false,
+            });
+            continue;
+        }
+        if in_block_comment || depth > 0 {
+            // continuation lines keep their offset from the line that opened them
+            let indent = (bracket_base.1 + width).saturating_sub(bracket_base.0);
+            if in_block_comment {
+                in_block_comment = !content.contains("*/");
+            } else {
+                depth = (depth + bracket_delta(split_comment(content).0)).max(0);
+            }
+            lines.push(Line {
+                level: 0,
+                code: format!("{}{}", " ".repeat(indent), content),
+                comment: None,
+                is_verbatim: true,
+                is_continuation: true,
+            });
+            continue;
+        }
+        let (code, comment) = split_comment(content);
+        let is_comment_line = code.trim().is_empty();
+        let level = if is_comment_line {
+            // comments follow the block they sit in without opening or closing one
+            match widths.iter().rposition(|block| *block <= width) {
+                Some(level) if widths[level] < width => level + 1,
+                Some(level) => level,
+                None => 0,
+            }
+        } else {
+            while widths.last().is_some_and(|block| *block > width) {
+                widths.pop();
+            }
+            if widths.last().is_none_or(|block| *block < width) {
+                widths.push(width);
+            }
+            widths.len() - 1
+        };
+        if level == 0 && !is_comment_line && !code.starts_with(':') {
+            in_asm = code.starts_with("asm ") || code.starts_with("asm\t");
+        }
+        let has_block_comment = code.contains("/*");
+        if has_block_comment && !code.contains("*/") {
+            in_block_comment = true;
+            bracket_base = (width, level * INDENT_WIDTH);
+        }
+        let delta = bracket_delta(code);
+        if delta > 0 {
+            depth = delta;
+            bracket_base = (width, level * INDENT_WIDTH);
+        }
+        // asm bodies, `: modifier` lines, block comments and the first line of a
+        // multi-line table keep their own spacing
+        let is_verbatim =
+            (in_asm && level > 0) || code.starts_with(':') || has_block_comment || delta > 0;
+        let (code, comment) = if is_verbatim && !is_comment_line {
+            (content.to_string(), None)
+        } else if is_comment_line {
+            (String::new(), comment.map(str::to_string))
+        } else {
+            (
+                format_code(code.trim_end()),
+                comment.map(|comment| comment.trim_end().to_string()),
+            )
+        };
+        lines.push(Line {
+            level,
+            code,
+            comment,
+            is_verbatim,
+            is_continuation: false,
+        });
+    }
+
+    align_comments(&mut lines);
+
+    let mut output = Vec::<String>::new();
+    let mut previous_level = None;
+    for line in lines {
+        if line.is_blank() {
+            // runs of blank lines collapse to one, and none start the file
+            if output.last().is_some_and(|last| !last.is_empty()) {
+                output.push(String::new());
+            }
+            continue;
+        }
+        let is_code = !line.code.is_empty() && !line.is_continuation;
+        let is_definition = is_code && line.level == 0 && !line.code.starts_with(':');
+        if is_definition && previous_level.is_some_and(|level| level > 0) {
+            // one blank line separates a definition, with its comments, from the block above
+            let mut insert_at = output.len();
+            while insert_at > 0 && output[insert_at - 1].starts_with("//") {
+                insert_at -= 1;
+            }
+            while insert_at > 0 && output[insert_at - 1].is_empty() {
+                insert_at -= 1;
+                output.remove(insert_at);
+            }
+            output.insert(insert_at, String::new());
+        }
+        if is_code {
+            previous_level = Some(line.level);
+        }
+        let mut text = format!("{}{}", " ".repeat(line.level * INDENT_WIDTH), line.code);
+        if let Some(comment) = &line.comment {
+            if !line.code.is_empty() && !text.ends_with(' ') {
+                text.push(' ');
+            }
+            text.push_str(comment);
+        }
+        output.push(text);
+    }
+    while output.last().is_some_and(|last| last.is_empty()) {
+        output.pop();
+    }
+    output
+}
+
+// trailing comments of consecutive code lines in the same block start at one column
+fn align_comments(lines: &mut [Line]) {
+    let mut start = 0;
+    while start < lines.len() {
+        let is_candidate = |line: &Line, level: usize| {
+            line.comment.is_some()
+                && !line.code.is_empty()
+                && !line.is_verbatim
+                && line.level == level
+        };
+        if !is_candidate(&lines[start], lines[start].level) {
+            start += 1;
+            continue;
+        }
+        let level = lines[start].level;
+        let mut end = start + 1;
+        while end < lines.len() && is_candidate(&lines[end], level) {
+            end += 1;
+        }
+        if end - start > 1 {
+            let column = lines[start..end]
+                .iter()
+                .map(|line| line.code.chars().count())
+                .max()
+                .unwrap_or_default()
+                + 1;
+            for line in &mut lines[start..end] {
+                let padding = column - line.code.chars().count();
+                line.code.push_str(&" ".repeat(padding));
+            }
+        }
+        start = end;
+    }
+}
+
+pub fn format_source(source: &str) -> String {
+    let mut formatted = format_lines(source).join("\n");
+    formatted.push('\n');
+    formatted
+}
+
+// edits turning `source` into its formatted form, limited to lines in `rows` when given.
+// formatting never adds or removes code lines, so the nth non-blank lines correspond
+pub fn format_edits(source: &str, rows: Option<(u32, u32)>) -> Vec<TextEdit> {
+    let original = source.lines().collect::<Vec<_>>();
+    let formatted = format_lines(source);
+    let original_code = original
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(row, _)| row)
+        .collect::<Vec<_>>();
+    let formatted_code = formatted
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(row, _)| row)
+        .collect::<Vec<_>>();
+    if original_code.len() != formatted_code.len() {
+        return Vec::new();
+    }
+    let in_rows =
+        |row: usize| rows.is_none_or(|(start, end)| start as usize <= row && row <= end as usize);
+    let mut edits = Vec::new();
+    let mut previous = (None::<usize>, None::<usize>);
+    for (original_row, formatted_row) in original_code.iter().zip(formatted_code.iter()) {
+        // the blank lines above each code line travel with it
+        let blank_start = previous.0.map_or(0, |row| row + 1);
+        let formatted_blanks = formatted_row - previous.1.map_or(0, |row| row + 1);
+        previous = (Some(*original_row), Some(*formatted_row));
+        if !in_rows(*original_row) {
+            continue;
+        }
+        let original_text = original[blank_start..=*original_row].join("\n");
+        let new_text = format!(
+            "{}{}",
+            "\n".repeat(formatted_blanks),
+            formatted[*formatted_row]
+        );
+        if original_text != new_text {
+            edits.push(TextEdit::new(
+                Range::new(
+                    Position::new(blank_start as u32, 0),
+                    Position::new(*original_row as u32, original[*original_row].len() as u32),
+                ),
+                new_text,
+            ));
+        }
+    }
+    // trailing blank lines and a missing final newline only matter to whole-file formatting
+    if let (None, Some(last_row)) = (rows, previous.0) {
+        let end_offset = source
+            .split_inclusive('\n')
+            .take(last_row)
+            .map(str::len)
+            .sum::<usize>()
+            + original[last_row].len();
+        if &source[end_offset..] != "\n" {
+            let line_count = source.split_inclusive('\n').count();
+            let end = if source.ends_with('\n') {
+                Position::new(line_count as u32, 0)
+            } else {
+                let last = source.split_inclusive('\n').next_back().unwrap_or_default();
+                Position::new(line_count.saturating_sub(1) as u32, last.len() as u32)
+            };
+            edits.push(TextEdit::new(
+                Range::new(
+                    Position::new(last_row as u32, original[last_row].len() as u32),
+                    end,
+                ),
+                "\n".to_string(),
+            ));
+        }
+    }
+    edits
+}
+
+// first words of lines that open an indented block
+const BLOCK_OPENERS: &[&str] = &[
+    "fn", "asm", "mode", "nmi", "irq", "vars", "data", "omni", "struct", "if", "else", "for",
+    "while", "do", "switch", "case", "default",
+];
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+fn first_word(code: &str) -> &str {
+    code.split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or("")
+}
+
+fn is_code(line: &str) -> bool {
+    let code = line.trim_start();
+    !code.is_empty() && !code.starts_with("//")
+}
+
+// the `while` closing a `do` loop sits at the same indentation as its `do`
+fn closes_do_loop(lines: &[&str], row: usize) -> bool {
+    let indent = leading_whitespace(lines[row]).len();
+    lines[..row]
+        .iter()
+        .rev()
+        .filter(|line| is_code(line))
+        .find(|line| leading_whitespace(line).len() <= indent)
+        .is_some_and(|line| {
+            leading_whitespace(line).len() == indent && first_word(line.trim_start()) == "do"
+        })
+}
+
+fn opens_block(lines: &[&str], row: usize) -> bool {
+    let (code, _) = split_comment(lines[row]);
+    let code = code.trim();
+    let code = code.strip_prefix("ct ").map_or(code, str::trim_start);
+    let word = first_word(code);
+    BLOCK_OPENERS.contains(&word)
+        && bracket_delta(code) <= 0
+        && !(word == "while" && closes_do_loop(lines, row))
+}
+
+fn indent_edit(row: usize, line: &str, indentation: String) -> Option<TextEdit> {
+    let current = leading_whitespace(line);
+    (current != indentation).then(|| TextEdit {
+        range: Range {
+            start: Position::new(row as u32, 0),
+            end: Position::new(row as u32, current.len() as u32),
+        },
+        new_text: indentation,
+    })
+}
+
+// indents the line started by Enter one level deeper than a block header above it.
+// `:` modifier lines belong to the header before them
+pub fn newline_edits(source: &str, row: u32) -> Vec<TextEdit> {
+    let lines = source.split('\n').collect::<Vec<_>>();
+    let row = row as usize;
+    let Some(line) = lines.get(row) else {
+        return Vec::new();
+    };
+    let Some(mut header) = (0..row).rev().find(|&r| !lines[r].trim().is_empty()) else {
+        return Vec::new();
+    };
+    if lines[header].trim_start().starts_with(':') {
+        match (0..header).rev().find(|&r| {
+            let code = lines[r].trim_start();
+            is_code(code) && !code.starts_with(':')
+        }) {
+            Some(r) => header = r,
+            None => return Vec::new(),
+        }
+    } else if !opens_block(&lines, header) {
+        return Vec::new();
+    }
+    let indentation = format!(
+        "{}{}",
+        leading_whitespace(lines[header]),
+        " ".repeat(INDENT_WIDTH)
+    );
+    indent_edit(row, line, indentation).into_iter().collect()
+}
+
+fn collect_keyword_rows(cursor: &mut TreeCursor, keyword: &str, rows: &mut Vec<usize>) {
+    loop {
+        let node = cursor.node();
+        if node.kind() == keyword {
+            rows.push(node.start_position().row);
+        }
+        if cursor.goto_first_child() {
+            collect_keyword_rows(cursor, keyword, rows);
+            cursor.goto_parent();
+        }
+        if !cursor.goto_next_sibling() {
+            break;
+        }
+    }
+}
+
+// moves a freshly typed `else` to the indentation of its `if`: the closest `if` or
+// `else if` line above whose block is still open at the cursor
+pub fn else_edits(source: &str, tree: &Tree, row: u32) -> Vec<TextEdit> {
+    let lines = source.split('\n').collect::<Vec<_>>();
+    let row = row as usize;
+    let Some(line) = lines.get(row) else {
+        return Vec::new();
+    };
+    if split_comment(line).0.trim() != "else" {
+        return Vec::new();
+    }
+    let mut if_rows = Vec::new();
+    collect_keyword_rows(&mut tree.walk(), "if", &mut if_rows);
+    let indent = leading_whitespace(line).len();
+    let mut block_indent = usize::MAX;
+    for r in (0..row).rev() {
+        let code = lines[r].trim_start();
+        if !is_code(code) {
+            continue;
+        }
+        let r_indent = leading_whitespace(lines[r]).len();
+        let is_if = matches!(first_word(code), "if" | "else") && if_rows.contains(&r);
+        if is_if && r_indent <= indent && r_indent < block_indent {
+            let indentation = leading_whitespace(lines[r]).to_string();
+            return indent_edit(row, line, indentation).into_iter().collect();
+        }
+        // a line at or left of every candidate so far closes their blocks
+        block_indent = block_indent.min(r_indent);
+        if block_indent == 0 {
+            break;
+        }
+    }
+    Vec::new()
+}
+
+#[derive(Debug, Clone)]
+pub struct FormatSettings {
+    // number of values per row when aligning data tables
+    pub table_columns: usize,
+}
+
+impl Default for FormatSettings {
+    fn default() -> Self {
+        Self { table_columns: 16 }
+    }
+}
+
+impl FormatSettings {
+    // reads `{ "format": { "tableColumns" } }`, optionally nested in "nesfab"
+    pub fn update(&mut self, value: &serde_json::Value) {
+        let format = value
+            .get("format")
+            .or_else(|| value.get("nesfab").and_then(|value| value.get("format")));
+        if let Some(format) = format {
+            if let Some(columns) = format.get("tableColumns").and_then(|value| value.as_u64()) {
+                self.table_columns = (columns as usize).max(1);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TableRow {
+    // values of a row, with the comment that ends it
+    Values(Vec<String>, Option<String>),
+    // blank and comment-only lines, kept as they are
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    // first and last row holding values, the brackets stay on their own lines
+    pub rows: (usize, usize),
+    indentation: String,
+    items: Vec<TableRow>,
+    trailing_comma: bool,
+}
+
+impl Table {
+    // the widest existing row
+    pub fn columns(&self) -> usize {
+        self.items
+            .iter()
+            .filter_map(|item| match item {
+                TableRow::Values(values, _) => Some(values.len()),
+                TableRow::Text(_) => None,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+fn parse_table(lines: &[&str], rows: (usize, usize)) -> Option<Table> {
+    let mut items = Vec::new();
+    let mut indentation = None;
+    let mut trailing_comma = false;
+    for line in &lines[rows.0..=rows.1] {
+        let (code, comment) = split_comment(line);
+        let code = code.trim();
+        if code.is_empty() {
+            items.push(TableRow::Text(line.trim().to_string()));
+            continue;
+        }
+        if code.contains(|c| "()[]{}\"'".contains(c)) {
+            return None;
+        }
+        indentation.get_or_insert_with(|| leading_whitespace(line).to_string());
+        trailing_comma = code.ends_with(',');
+        let mut values = code.split(',').map(str::trim).collect::<Vec<_>>();
+        if trailing_comma {
+            values.pop();
+        }
+        if values.iter().any(|value| value.is_empty()) {
+            return None;
+        }
+        let values = values.into_iter().map(str::to_string).collect();
+        let comment = comment.map(|comment| comment.trim_end().to_string());
+        items.push(TableRow::Values(values, comment));
+    }
+    Some(Table {
+        rows,
+        indentation: indentation?,
+        items,
+        trailing_comma,
+    })
+}
+
+// the innermost multi-line bracket around `range`, or the selected lines themselves when
+// they are nothing but comma separated values
+pub fn find_table(source: &str, range: Range) -> Option<Table> {
+    let lines = source.split('\n').collect::<Vec<_>>();
+    let start = range.start.line as usize;
+    let end = range.end.line as usize;
+    if end >= lines.len() {
+        return None;
+    }
+    let mut open = Vec::new();
+    let mut innermost = None;
+    for (row, line) in lines.iter().enumerate() {
+        for token in tokenize(split_comment(line).0) {
+            match token {
+                Token::Open(_) => open.push(row),
+                Token::Close(_) => {
+                    let Some(opener) = open.pop() else {
+                        continue;
+                    };
+                    if opener + 1 < row && opener <= start && end <= row {
+                        innermost = innermost.max(Some((opener, row)));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    if let Some((opener, closer)) = innermost {
+        // values sharing a line with a bracket are left for the user to split
+        let after_open = lines[opener]
+            .rsplit(['(', '[', '{'])
+            .next()
+            .unwrap_or_default();
+        let before_close = lines[closer].trim_start();
+        if split_comment(after_open).0.trim().is_empty()
+            && before_close.starts_with([')', ']', '}'])
+        {
+            return parse_table(&lines, (opener + 1, closer - 1));
+        }
+        return None;
+    }
+    (range.start != range.end)
+        .then(|| parse_table(&lines, (start, end)))
+        .flatten()
+}
+
+fn is_numeric(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '$' | '%' | '-' | '+'))
+}
+
+// lays the table out in rows of `columns` values padded to the widest value, numbers
+// right-aligned. a comment closes its row so it stays after the value it followed
+pub fn align_table(source: &str, table: &Table, columns: usize) -> Option<TextEdit> {
+    let lines = source.split('\n').collect::<Vec<_>>();
+    let width = table
+        .items
+        .iter()
+        .flat_map(|item| match item {
+            TableRow::Values(values, _) => values.iter().map(|value| value.chars().count()).max(),
+            TableRow::Text(_) => None,
+        })
+        .max()
+        .unwrap_or_default();
+    let total = table
+        .items
+        .iter()
+        .map(|item| match item {
+            TableRow::Values(values, _) => values.len(),
+            TableRow::Text(_) => 0,
+        })
+        .sum::<usize>();
+    let mut rows = Vec::<(String, Option<String>)>::new();
+    let mut cells = Vec::<String>::new();
+    let mut count = 0;
+    for item in &table.items {
+        let (values, comment) = match item {
+            TableRow::Values(values, comment) => (values, comment),
+            TableRow::Text(text) => {
+                if !cells.is_empty() {
+                    rows.push((cells.join(" "), None));
+                    cells.clear();
+                }
+                rows.push((String::new(), Some(text.clone())));
+                continue;
+            }
+        };
+        for (index, value) in values.iter().enumerate() {
+            count += 1;
+            let is_last = count == total;
+            let comma = if is_last && !table.trailing_comma {
+                ""
+            } else {
+                ","
+            };
+            let cell = if is_numeric(value) {
+                format!("{value:>width$}{comma}")
+            } else {
+                format!("{:<1$}", format!("{value}{comma}"), width + 1)
+            };
+            cells.push(cell);
+            let closes_row = index + 1 == values.len() && comment.is_some();
+            if cells.len() == columns || closes_row || is_last {
+                let comment = closes_row.then(|| comment.clone()).flatten();
+                rows.push((cells.join(" ").trim_end().to_string(), comment));
+                cells.clear();
+            }
+        }
+    }
+    let column = rows
+        .iter()
+        .filter(|(code, _)| !code.is_empty())
+        .map(|(code, _)| code.chars().count())
+        .max()
+        .unwrap_or_default();
+    let mut new_text = String::new();
+    for (code, comment) in rows {
+        let line = match comment {
+            Some(comment) if code.is_empty() => comment,
+            Some(comment) => format!("{code:<column$} {comment}"),
+            None => code,
+        };
+        if !line.is_empty() {
+            new_text.push_str(&table.indentation);
+        }
+        new_text.push_str(&line);
+        new_text.push('\n');
+    }
+    let (first, last) = table.rows;
+    let old_text = lines[first..=last].join("\n") + "\n";
+    if old_text == new_text {
+        return None;
+    }
+    let end = match lines.get(last + 1) {
+        Some(_) => Position::new(last as u32 + 1, 0),
+        None => {
+            new_text.pop();
+            Position::new(last as u32, utf16_len(lines[last]))
+        }
+    };
+    Some(TextEdit {
+        range: Range {
+            start: Position::new(first as u32, 0),
+            end,
+        },
+        new_text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+// player state
+vars
+  U x=1 // position
+  U speed   =  2 // pixels per frame
+
+
+fn update(U a,U b)
+  if a==b
+      x+=speed
+  else
+      x = -1
+  // done
+mode main()
+: nmi main_nmi
+  update(1,2)
+  goto mode main()
+asm fn clear()
+  : employs
+    lda  #0   ; low byte
+    sta  $2000
+    rts
+data /levels
+  [] level
+    (U[4]($00,$01,
+         $02,  $03))
+ct U[] names = U[](\"a  ,b\",  'c' )
+/* keep   this
+     spacing */
+";
+
+    // applies non-overlapping edits by walking them from the end of the file
+    fn apply(source: &str, edits: &[TextEdit]) -> String {
+        let offset = |position: &Position| {
+            source
+                .split_inclusive('\n')
+                .take(position.line as usize)
+                .map(str::len)
+                .sum::<usize>()
+                + position.character as usize
+        };
+        let mut edits = edits.to_vec();
+        edits.sort_by_key(|edit| edit.range.start);
+        let mut result = source.to_string();
+        for edit in edits.iter().rev() {
+            let range = offset(&edit.range.start)..offset(&edit.range.end);
+            result.replace_range(range, &edit.new_text);
+        }
+        result
+    }
+
+    #[test]
+    fn format_source_is_idempotent() {
+        let formatted = format_source(SOURCE);
+        assert_eq!(format_source(&formatted), formatted);
+        assert!(format_edits(&formatted, None).is_empty());
+    }
+
+    #[test]
+    fn format_edits_produce_the_formatted_source() {
+        assert_eq!(
+            apply(SOURCE, &format_edits(SOURCE, None)),
+            format_source(SOURCE)
+        );
+        let unterminated = SOURCE.trim_end();
+        assert_eq!(
+            apply(unterminated, &format_edits(unterminated, None)),
+            format_source(unterminated)
+        );
+    }
+
+    #[test]
+    fn format_source_normalizes_code() {
+        let formatted = format_source(SOURCE);
+        let lines = formatted.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"    U x = 1     // position"));
+        assert!(lines.contains(&"    U speed = 2 // pixels per frame"));
+        assert!(lines.contains(&"fn update(U a, U b)"));
+        assert!(lines.contains(&"    if a == b"));
+        assert!(lines.contains(&"        x += speed"));
+        assert!(lines.contains(&"        x = -1"));
+        // definitions after an indented block get one blank line, runs of them collapse
+        assert!(formatted.contains("pixels per frame\n\nfn update"));
+        assert!(formatted.contains("// done\n\nmode main()"));
+    }
+
+    #[test]
+    fn format_source_preserves_verbatim_lines() {
+        let formatted = format_source(SOURCE);
+        for line in [
+            ": nmi main_nmi",
+            "    : employs",
+            "        lda  #0   ; low byte",
+            "        sta  $2000",
+            "        (U[4]($00,$01,",
+            "             $02,  $03))",
+            "ct U[] names = U[](\"a  ,b\", 'c')",
+            "/* keep   this",
+            "     spacing */",
+            "// player state",
+        ] {
+            assert!(
+                formatted.lines().any(|text| text == line),
+                "{line:?} in\n{formatted}"
+            );
+        }
+    }
+
+    #[test]
+    fn range_edits_stay_within_the_rows() {
+        let edits = format_edits(SOURCE, Some((7, 8)));
+        assert!(!edits.is_empty());
+        assert!(edits
+            .iter()
+            .all(|edit| (7..=8).contains(&edit.range.end.line)));
+    }
+
+    const TABLE: &str = "\
+data /levels
+    [] tiles
+        (U[](
+            $00,$01,$02,
+            $03,   $04, // row end
+            $05,$06,$07,$08,$09,
+        ))
+";
+
+    fn rows(start: u32, end: u32) -> Range {
+        Range {
+            start: Position::new(start, 0),
+            end: Position::new(end, 0),
+        }
+    }
+
+    #[test]
+    fn find_table_reads_the_rows_inside_the_brackets() {
+        let table = find_table(TABLE, rows(4, 4)).unwrap();
+        assert_eq!(table.rows, (3, 5));
+        assert_eq!(table.columns(), 5);
+        // values sharing a line with a bracket are not a table
+        assert!(find_table("(U[]($00,\n    $01))\n", rows(1, 1)).is_none());
+        // nor is a selection holding strings
+        assert!(find_table("\"a\", \"b\",\n\"c\",\n", rows(0, 1)).is_none());
+    }
+
+    #[test]
+    fn align_table_wraps_at_table_columns() {
+        let table = find_table(TABLE, rows(4, 4)).unwrap();
+        let edit = align_table(TABLE, &table, 4).unwrap();
+        assert_eq!(edit.range, rows(3, 6));
+        assert_eq!(
+            edit.new_text,
+            "            $00, $01, $02, $03,\n            \
+             $04,                // row end\n            \
+             $05, $06, $07, $08,\n            \
+             $09,\n"
+        );
+        let applied = apply(TABLE, &[edit]);
+        let table = find_table(&applied, rows(4, 4)).unwrap();
+        assert!(align_table(&applied, &table, 4).is_none());
+    }
+
+    #[test]
+    fn align_table_measures_width_in_chars() {
+        let source = "été, a,\nb, c, // café\nd,\n";
+        let table = find_table(source, rows(0, 2)).unwrap();
+        let edit = align_table(source, &table, 2).unwrap();
+        assert_eq!(edit.new_text, "été, a,\nb,   c, // café\nd,\n");
+    }
+
+    #[test]
+    fn align_comments_measures_width_in_chars() {
+        let formatted = format_source("vars\n    U[] a = \"é\" // a\n    U[] b = \"e\" // b\n");
+        assert!(formatted.contains("U[] a = \"é\" // a\n"), "{formatted}");
+        assert!(formatted.contains("U[] b = \"e\" // b\n"), "{formatted}");
+    }
+
+    fn indentation(source: &str, row: u32, edits: Vec<TextEdit>) -> String {
+        let line = source.split('\n').nth(row as usize).unwrap();
+        apply(
+            line,
+            &edits
+                .into_iter()
+                .map(|edit| TextEdit {
+                    range: Range {
+                        start: Position::new(0, edit.range.start.character),
+                        end: Position::new(0, edit.range.end.character),
+                    },
+                    ..edit
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn newline_edits_indent_after_block_headers() {
+        for header in [
+            "fn update(U a)",
+            "mode main()",
+            "vars",
+            "if a == b",
+            "else",
+            "while x",
+        ] {
+            let source = format!("    {header}\n\n");
+            assert_eq!(
+                indentation(&source, 1, newline_edits(&source, 1)),
+                "        ",
+                "{header}"
+            );
+        }
+        // modifier lines belong to the header above them
+        let source = "mode main()\n: nmi main_nmi\nx";
+        assert_eq!(indentation(source, 2, newline_edits(source, 2)), "    x");
+        // plain statements, open brackets and the `while` of a `do` loop leave the line alone
+        for source in ["    x = 1\n", "if f(a,\n", "do\n    x += 1\nwhile x\n"] {
+            let row = source.matches('\n').count() as u32;
+            assert!(newline_edits(source, row).is_empty(), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn else_edits_realign_else_with_its_if() {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_nesfab::language())
+            .unwrap();
+        let source = "\
+fn update(U a)
+    if a
+        if a == 1
+            x = 1
+        else
+    else";
+        let tree = parser.parse(source, None).unwrap();
+        // the inner `if` is still open under the first `else`
+        assert!(else_edits(source, &tree, 4).is_empty());
+        let source = source.replacen("        else", "            else", 1);
+        let tree = parser.parse(&source, None).unwrap();
+        assert_eq!(
+            indentation(&source, 4, else_edits(&source, &tree, 4)),
+            "        else"
+        );
+        // the outer `else` already sits under the `if` it closes
+        assert!(else_edits(&source, &tree, 5).is_empty());
+        assert!(else_edits(&source, &tree, 3).is_empty());
+    }
+}
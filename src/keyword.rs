@@ -0,0 +1,281 @@
+use tree_sitter::{Node, Point, Tree};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    TopLevel,
+    // inside a vars, data, omni or struct block
+    Group,
+    // inside a fn, mode, nmi or irq body
+    Statement,
+    // somewhere keywords are never valid, e.g. after an expression has started
+    Expression,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeContext {
+    pub scope: Scope,
+    // the previous line at the same indentation opens an `if` or `else if`
+    pub after_if: bool,
+    // the enclosing block is a `switch`
+    pub in_switch: bool,
+    pub in_loop: bool,
+    // inside a `case` or `default` branch
+    pub in_case: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    TopLevel,
+    Statement,
+    // valid at the top level and inside bodies
+    Anywhere,
+    AfterIf,
+    InSwitch,
+    InLoop,
+    // inside a loop or a switch branch
+    Breakable,
+}
+
+#[derive(Debug)]
+pub struct Keyword {
+    pub label: &'static str,
+    pub detail: &'static str,
+    pub insert_text: &'static str,
+    pub placement: Placement,
+    pub is_snippet: bool,
+}
+
+impl Keyword {
+    pub fn is_allowed(&self, context: &CodeContext) -> bool {
+        match (self.placement, context.scope) {
+            (_, Scope::Expression) => false,
+            (Placement::TopLevel, scope) => scope == Scope::TopLevel,
+            (Placement::Anywhere, scope) => scope != Scope::Group,
+            (_, scope) if scope != Scope::Statement => false,
+            (Placement::Statement, _) => true,
+            (Placement::AfterIf, _) => context.after_if,
+            (Placement::InSwitch, _) => context.in_switch,
+            (Placement::InLoop, _) => context.in_loop,
+            (Placement::Breakable, _) => context.in_loop || context.in_case,
+        }
+    }
+}
+
+const fn keyword(label: &'static str, detail: &'static str, placement: Placement) -> Keyword {
+    Keyword {
+        label,
+        detail,
+        insert_text: label,
+        placement,
+        is_snippet: false,
+    }
+}
+
+const fn snippet(
+    label: &'static str,
+    detail: &'static str,
+    insert_text: &'static str,
+    placement: Placement,
+) -> Keyword {
+    Keyword {
+        label,
+        detail,
+        insert_text,
+        placement,
+        is_snippet: true,
+    }
+}
+
+pub static KEYWORDS: &[Keyword] = &[
+    keyword("fn", "function definition", Placement::TopLevel),
+    keyword(
+        "asm fn",
+        "assembly function definition",
+        Placement::TopLevel,
+    ),
+    keyword("mode", "mode definition", Placement::TopLevel),
+    keyword("nmi", "NMI handler definition", Placement::TopLevel),
+    keyword("irq", "IRQ handler definition", Placement::TopLevel),
+    keyword("vars", "global variable group", Placement::TopLevel),
+    keyword("data", "constant data group", Placement::TopLevel),
+    keyword("omni", "data present in every bank", Placement::TopLevel),
+    keyword("struct", "struct definition", Placement::TopLevel),
+    keyword("charmap", "character map definition", Placement::TopLevel),
+    keyword("ct", "compile-time constant", Placement::Anywhere),
+    keyword("if", "conditional", Placement::Statement),
+    keyword("else", "alternative of an if", Placement::AfterIf),
+    keyword("for", "loop", Placement::Statement),
+    keyword("while", "loop", Placement::Statement),
+    keyword("do", "loop", Placement::Statement),
+    keyword("switch", "multi-way branch", Placement::Statement),
+    keyword("case", "branch of a switch", Placement::InSwitch),
+    keyword(
+        "default",
+        "fallback branch of a switch",
+        Placement::InSwitch,
+    ),
+    keyword("goto mode", "switch to another mode", Placement::Statement),
+    keyword("return", "return from the function", Placement::Statement),
+    keyword("fence", "wait for the next NMI", Placement::Statement),
+    keyword("break", "exit the loop or switch", Placement::Breakable),
+    keyword("continue", "next loop iteration", Placement::InLoop),
+    snippet(
+        "fn ...",
+        "function skeleton",
+        "fn ${1:name}(${2})\n    ${0:return}",
+        Placement::TopLevel,
+    ),
+    snippet(
+        "mode ... nmi",
+        "mode with an nmi handler",
+        "mode ${1:main}()\n: nmi ${1:main}_nmi\n    $0\n\nnmi ${1:main}_nmi()\n    ${2}",
+        Placement::TopLevel,
+    ),
+    snippet(
+        "vars /...",
+        "global variable group",
+        "vars /${1:group}\n    ${2:U} ${3:name}$0",
+        Placement::TopLevel,
+    ),
+];
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+// first token of `row`, None for blank and comment-only lines
+fn first_token<'a>(tree: &'a Tree, lines: &[&str], row: usize) -> Option<Node<'a>> {
+    let line = lines.get(row)?;
+    if line.trim().is_empty() {
+        return None;
+    }
+    let point = Point::new(row, indentation(line));
+    tree.root_node()
+        .descendant_for_point_range(point, point)
+        .filter(|node| node.kind() != "comment")
+}
+
+// the keyword a block starts with: the definitions by their node kind, statements by their
+// first token, which tree-sitter names after the keyword itself
+fn block_keyword(node: &Node) -> Option<&'static str> {
+    match node.kind() {
+        "function_definition" | "asm_function_definition" => return Some("fn"),
+        "vars_definition" => return Some("vars"),
+        _ => {}
+    }
+    let first = node.child(0)?;
+    [
+        "fn", "mode", "nmi", "irq", "asm", "vars", "data", "omni", "struct", "if", "else", "for",
+        "while", "do", "switch", "case", "default",
+    ]
+    .into_iter()
+    .find(|keyword| first.kind() == *keyword)
+}
+
+// inside a bracket opened before `point` and not closed before it
+fn is_in_brackets(tree: &Tree, point: Point) -> bool {
+    let mut node = tree.root_node().descendant_for_point_range(point, point);
+    while let Some(current) = node {
+        let mut cursor = current.walk();
+        let depth = current
+            .children(&mut cursor)
+            .filter(|child| child.end_position() <= point && !child.is_missing())
+            .map(|child| match child.kind() {
+                "(" | "[" | "{" => 1,
+                ")" | "]" | "}" => -1,
+                _ => 0,
+            })
+            .sum::<isize>();
+        if depth > 0 {
+            return true;
+        }
+        node = current.parent();
+    }
+    false
+}
+
+// the previous statement at the same indentation is an `if` or `else if`
+fn is_after_if(tree: &Tree, lines: &[&str], row: usize, indent: usize) -> bool {
+    for previous in (0..row).rev() {
+        let Some(token) = first_token(tree, lines, previous) else {
+            continue;
+        };
+        let previous_indent = indentation(lines[previous]);
+        if previous_indent > indent {
+            continue;
+        }
+        if previous_indent < indent {
+            return false;
+        }
+        return match token.kind() {
+            "if" => true,
+            "else" => token.next_sibling().is_some_and(|next| next.kind() == "if"),
+            _ => false,
+        };
+    }
+    false
+}
+
+// the scope comes from the syntax tree: the blocks enclosing the cursor are the ancestors of
+// the previous line's first token that start left of the cursor's indentation
+pub fn code_context(source: &str, tree: &Tree, row: usize, column: usize) -> CodeContext {
+    let lines = source.lines().collect::<Vec<_>>();
+    let line = lines.get(row).copied().unwrap_or("");
+    let prefix = line.get(..column.min(line.len())).unwrap_or(line);
+    let mut context = CodeContext {
+        scope: Scope::Expression,
+        after_if: false,
+        in_switch: false,
+        in_loop: false,
+        in_case: false,
+    };
+    // keywords only start a line; anything but the word being typed rules them out
+    let typed = prefix.trim_start();
+    if !typed.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return context;
+    }
+    let point = Point::new(row, prefix.len() - typed.len());
+    if is_in_brackets(tree, point) {
+        return context;
+    }
+    let mut indent = indentation(prefix);
+    if typed.is_empty() && prefix.len() < line.len() {
+        indent = indentation(line);
+    }
+    if indent == 0 {
+        context.scope = Scope::TopLevel;
+        return context;
+    }
+    context.scope = Scope::Statement;
+    context.after_if = is_after_if(tree, &lines, row, indent);
+    let Some(anchor) = (0..row)
+        .rev()
+        .find_map(|previous| first_token(tree, &lines, previous))
+    else {
+        return context;
+    };
+    let mut is_parent = true;
+    let mut node = Some(anchor);
+    while let Some(current) = node {
+        node = current.parent();
+        if current.start_position().column >= indent {
+            continue;
+        }
+        let Some(keyword) = block_keyword(&current) else {
+            continue;
+        };
+        match keyword {
+            "switch" if is_parent => context.in_switch = true,
+            "for" | "while" | "do" => context.in_loop = true,
+            "case" | "default" => context.in_case = true,
+            "vars" | "data" | "omni" | "struct" => {
+                context.scope = Scope::Group;
+                return context;
+            }
+            "fn" | "mode" | "nmi" | "irq" | "asm" => return context,
+            _ => {}
+        }
+        is_parent = false;
+    }
+    context
+}
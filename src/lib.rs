@@ -1,3 +1,16 @@
+pub mod asm;
 pub mod backend;
+pub mod builtin;
 pub mod cfg;
+pub mod charmap;
+pub mod compiler;
+pub mod format;
+pub mod keyword;
+pub mod number;
+pub mod opcode;
+pub mod palette;
+pub mod preview;
+pub mod resource;
 pub mod symbol;
+pub mod usage;
+pub mod utf16;
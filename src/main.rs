@@ -6,6 +6,8 @@ async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(|client| Backend::new(client));
+    let (service, socket) = LspService::build(|client| Backend::new(client))
+        .custom_method("nesfab/bankUsage", Backend::bank_usage)
+        .finish();
     Server::new(stdin, stdout, socket).serve(service).await;
 }
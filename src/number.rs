@@ -0,0 +1,306 @@
+use crate::utf16::utf16_len;
+use tower_lsp::lsp_types::{Position, Range};
+
+// NESFab fixed-point types have at most 3 fractional bytes
+const MAX_FRACTION_BITS: u32 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base {
+    Decimal,
+    Hex,
+    Binary,
+}
+
+impl Base {
+    pub const ALL: [Base; 3] = [Base::Decimal, Base::Hex, Base::Binary];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Base::Decimal => "decimal",
+            Base::Hex => "hex",
+            Base::Binary => "binary",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NumericLiteral {
+    pub text: String,
+    pub range: Range,
+    pub base: Base,
+    // the value times 2^fraction_bits
+    value: u128,
+    fraction_bits: u32,
+}
+
+impl NumericLiteral {
+    fn parse(text: &str) -> Option<(Base, u128, u32)> {
+        let (base, digits) = match text.as_bytes().first()? {
+            b'$' => (Base::Hex, &text[1..]),
+            b'%' => (Base::Binary, &text[1..]),
+            _ => (Base::Decimal, text),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() || (digits.contains('.') && fraction.is_empty()) {
+            return None;
+        }
+        let radix = match base {
+            Base::Decimal => 10,
+            Base::Hex => 16,
+            Base::Binary => 2,
+        };
+        let whole = u128::from_str_radix(whole, radix).ok()?;
+        if fraction.is_empty() {
+            return Some((base, whole, 0));
+        }
+        let numerator = u128::from_str_radix(fraction, radix).ok()?;
+        let (numerator, bits) = match base {
+            Base::Hex => (numerator, 4 * fraction.len() as u32),
+            Base::Binary => (numerator, fraction.len() as u32),
+            // n / 10^k is n / 5^k / 2^k, exact in binary only when 5^k divides n
+            Base::Decimal => {
+                let places = fraction.len() as u32;
+                let power = 5u128.checked_pow(places)?;
+                if numerator % power != 0 {
+                    return None;
+                }
+                let mut numerator = numerator / power;
+                let mut bits = places;
+                while bits > 0 && numerator % 2 == 0 {
+                    numerator /= 2;
+                    bits -= 1;
+                }
+                (numerator, bits)
+            }
+        };
+        if bits > MAX_FRACTION_BITS {
+            return None;
+        }
+        let value = whole.checked_shl(bits)? | numerator;
+        (value >> bits == whole).then_some((base, value, bits))
+    }
+
+    // hex and binary are padded to full bytes on both sides of the point
+    pub fn to_base(&self, base: Base) -> String {
+        let whole = self.value >> self.fraction_bits;
+        let fraction = self.value & ((1 << self.fraction_bits) - 1);
+        match base {
+            Base::Decimal => {
+                let mut text = whole.to_string();
+                if self.fraction_bits > 0 {
+                    let places = self.fraction_bits as usize;
+                    let digits = fraction * 5u128.pow(self.fraction_bits);
+                    let digits = format!("{digits:0places$}");
+                    let digits = digits.trim_end_matches('0');
+                    text.push('.');
+                    text.push_str(if digits.is_empty() { "0" } else { digits });
+                }
+                text
+            }
+            Base::Hex => {
+                let mut text = format!("{whole:X}");
+                if text.len() % 2 == 1 {
+                    text.insert(0, '0');
+                }
+                if self.fraction_bits > 0 {
+                    let places = self.fraction_bits.div_ceil(8) * 2;
+                    let fraction = fraction << (4 * places - self.fraction_bits);
+                    text.push_str(&format!(".{fraction:0width$X}", width = places as usize));
+                }
+                format!("${text}")
+            }
+            Base::Binary => {
+                let mut text = format!("{whole:b}");
+                let width = text.len().div_ceil(8) * 8;
+                text = format!("{text:0>width$}");
+                if self.fraction_bits > 0 {
+                    let places = self.fraction_bits.div_ceil(8) * 8;
+                    let fraction = fraction << (places - self.fraction_bits);
+                    let places = places as usize;
+                    text.push_str(&format!(".{fraction:0places$b}"));
+                }
+                format!("%{text}")
+            }
+        }
+    }
+
+    pub fn markdown(&self) -> String {
+        Base::ALL
+            .iter()
+            .map(|base| format!("- {}: `{}`", base.name(), self.to_base(*base)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+// literals outside comments and strings. `%` only starts a literal where an operand is
+// expected, so `a %10` stays a modulo
+pub fn collect_numeric_literals(source: &str) -> Vec<NumericLiteral> {
+    let mut literals = Vec::new();
+    let mut in_block_comment = false;
+    for (row, line) in source.lines().enumerate() {
+        let bytes = line.as_bytes();
+        let mut index = 0;
+        let mut previous = None;
+        while index < bytes.len() {
+            let byte = bytes[index];
+            if in_block_comment {
+                if line[index..].starts_with("*/") {
+                    in_block_comment = false;
+                    index += 2;
+                } else {
+                    index += 1;
+                }
+                continue;
+            }
+            if line[index..].starts_with("//") {
+                break;
+            }
+            if line[index..].starts_with("/*") {
+                in_block_comment = true;
+                index += 2;
+                continue;
+            }
+            let is_operand = !previous.is_some_and(|previous: u8| {
+                is_identifier_byte(previous) || matches!(previous, b')' | b']')
+            });
+            match byte {
+                b'"' | b'\'' => {
+                    index += 1;
+                    while index < bytes.len() && bytes[index] != byte {
+                        index += if bytes[index] == b'\\' { 2 } else { 1 };
+                    }
+                    index += 1;
+                }
+                b'$' | b'%' | b'0'..=b'9' if byte != b'%' || is_operand => {
+                    let start = index;
+                    index += 1;
+                    while index < bytes.len()
+                        && (is_identifier_byte(bytes[index]) || bytes[index] == b'.')
+                    {
+                        index += 1;
+                    }
+                    let text = &line[start..index];
+                    if let Some((base, value, fraction_bits)) = NumericLiteral::parse(text) {
+                        literals.push(NumericLiteral {
+                            text: text.to_string(),
+                            range: Range {
+                                start: Position::new(row as u32, utf16_len(&line[..start])),
+                                end: Position::new(row as u32, utf16_len(&line[..index])),
+                            },
+                            base,
+                            value,
+                            fraction_bits,
+                        });
+                    }
+                }
+                _ if is_identifier_byte(byte) => {
+                    while index < bytes.len() && is_identifier_byte(bytes[index]) {
+                        index += 1;
+                    }
+                }
+                _ => index += 1,
+            }
+            let last = bytes[index.min(bytes.len()) - 1];
+            if !last.is_ascii_whitespace() {
+                previous = Some(last);
+            }
+        }
+    }
+    literals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(text: &str) -> NumericLiteral {
+        let literals = collect_numeric_literals(text);
+        assert_eq!(literals.len(), 1, "{text:?}");
+        literals.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn parse_reads_each_base() {
+        assert_eq!(NumericLiteral::parse("42"), Some((Base::Decimal, 42, 0)));
+        assert_eq!(NumericLiteral::parse("$2A"), Some((Base::Hex, 42, 0)));
+        assert_eq!(
+            NumericLiteral::parse("%101010"),
+            Some((Base::Binary, 42, 0))
+        );
+        assert_eq!(NumericLiteral::parse("1.5"), Some((Base::Decimal, 3, 1)));
+        assert_eq!(NumericLiteral::parse("$1.8"), Some((Base::Hex, 0x18, 4)));
+        assert_eq!(NumericLiteral::parse("%1.1"), Some((Base::Binary, 3, 1)));
+    }
+
+    #[test]
+    fn parse_rejects_inexact_and_malformed_literals() {
+        // 0.1 has no finite binary fraction
+        assert_eq!(NumericLiteral::parse("0.1"), None);
+        assert_eq!(NumericLiteral::parse("1."), None);
+        assert_eq!(NumericLiteral::parse("$"), None);
+        assert_eq!(NumericLiteral::parse("$G"), None);
+        // more than 3 fractional bytes
+        assert_eq!(NumericLiteral::parse("$1.00000001"), None);
+    }
+
+    #[test]
+    fn to_base_pads_hex_and_binary_to_bytes() {
+        let value = literal("42");
+        assert_eq!(value.to_base(Base::Decimal), "42");
+        assert_eq!(value.to_base(Base::Hex), "$2A");
+        assert_eq!(value.to_base(Base::Binary), "%00101010");
+        assert_eq!(literal("$100").to_base(Base::Binary), "%0000000100000000");
+        assert_eq!(literal("%1").to_base(Base::Hex), "$01");
+    }
+
+    #[test]
+    fn fixed_point_values_round_trip() {
+        for text in [
+            "1.5",
+            "0.25",
+            "3.125",
+            "$1.8",
+            "$A.01",
+            "%1.01",
+            "255.99609375",
+        ] {
+            let value = literal(text);
+            for base in Base::ALL {
+                let converted = literal(&value.to_base(base));
+                for other in Base::ALL {
+                    assert_eq!(
+                        converted.to_base(other),
+                        value.to_base(other),
+                        "{text} through {}",
+                        base.name()
+                    );
+                }
+            }
+        }
+        assert_eq!(literal("1.5").to_base(Base::Hex), "$01.80");
+        assert_eq!(literal("$1.8").to_base(Base::Decimal), "1.5");
+        assert_eq!(literal("0.25").to_base(Base::Binary), "%00000000.01000000");
+    }
+
+    #[test]
+    fn collect_skips_comments_strings_and_modulo() {
+        let source = "U a = b %10 + %1 // $FF\n\"$10\" /* 2 */ 3";
+        let texts = collect_numeric_literals(source)
+            .into_iter()
+            .map(|literal| literal.text)
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["10", "%1", "3"]);
+    }
+
+    #[test]
+    fn collect_uses_utf16_columns() {
+        let literal = literal("\"é😀\" $FF");
+        assert_eq!(literal.range.start, Position::new(0, 6));
+        assert_eq!(literal.range.end, Position::new(0, 9));
+    }
+}
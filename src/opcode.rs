@@ -0,0 +1,643 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressingMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl AddressingMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AddressingMode::Implied => "implied",
+            AddressingMode::Accumulator => "accumulator",
+            AddressingMode::Immediate => "immediate",
+            AddressingMode::ZeroPage => "zero page",
+            AddressingMode::ZeroPageX => "zero page,X",
+            AddressingMode::ZeroPageY => "zero page,Y",
+            AddressingMode::Absolute => "absolute",
+            AddressingMode::AbsoluteX => "absolute,X",
+            AddressingMode::AbsoluteY => "absolute,Y",
+            AddressingMode::Indirect => "indirect",
+            AddressingMode::IndirectX => "(indirect,X)",
+            AddressingMode::IndirectY => "(indirect),Y",
+            AddressingMode::Relative => "relative",
+        }
+    }
+    pub fn syntax(&self) -> &'static str {
+        match self {
+            AddressingMode::Implied => "",
+            AddressingMode::Accumulator => "a",
+            AddressingMode::Immediate => "#value",
+            AddressingMode::ZeroPage => "zp",
+            AddressingMode::ZeroPageX => "zp, x",
+            AddressingMode::ZeroPageY => "zp, y",
+            AddressingMode::Absolute => "addr",
+            AddressingMode::AbsoluteX => "addr, x",
+            AddressingMode::AbsoluteY => "addr, y",
+            AddressingMode::Indirect => "(addr)",
+            AddressingMode::IndirectX => "(zp, x)",
+            AddressingMode::IndirectY => "(zp), y",
+            AddressingMode::Relative => "label",
+        }
+    }
+    pub fn snippet(&self) -> &'static str {
+        match self {
+            AddressingMode::Implied => "",
+            AddressingMode::Accumulator => "a",
+            AddressingMode::Immediate => "#${1:value}",
+            AddressingMode::ZeroPage => "${1:zp}",
+            AddressingMode::ZeroPageX => "${1:zp}, x",
+            AddressingMode::ZeroPageY => "${1:zp}, y",
+            AddressingMode::Absolute => "${1:addr}",
+            AddressingMode::AbsoluteX => "${1:addr}, x",
+            AddressingMode::AbsoluteY => "${1:addr}, y",
+            AddressingMode::Indirect => "(${1:addr})",
+            AddressingMode::IndirectX => "(${1:zp}, x)",
+            AddressingMode::IndirectY => "(${1:zp}), y",
+            AddressingMode::Relative => "${1:label}",
+        }
+    }
+    pub fn bytes(&self) -> u8 {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 1,
+            AddressingMode::Immediate
+            | AddressingMode::ZeroPage
+            | AddressingMode::ZeroPageX
+            | AddressingMode::ZeroPageY
+            | AddressingMode::IndirectX
+            | AddressingMode::IndirectY
+            | AddressingMode::Relative => 2,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Encoding {
+    pub mode: AddressingMode,
+    pub opcode: u8,
+    pub cycles: u8,
+    // +1 cycle when the effective address crosses a page boundary
+    pub page_penalty: bool,
+}
+
+#[derive(Debug)]
+pub struct Instruction {
+    pub mnemonic: &'static str,
+    pub description: &'static str,
+    pub flags: &'static str,
+    pub encodings: &'static [Encoding],
+}
+
+impl Instruction {
+    pub fn is_branch(&self) -> bool {
+        self.encodings
+            .iter()
+            .any(|encoding| encoding.mode == AddressingMode::Relative)
+    }
+    pub fn markdown(&self) -> String {
+        let rows = self
+            .encodings
+            .iter()
+            .map(|encoding| {
+                let cycles = if encoding.mode == AddressingMode::Relative {
+                    format!("{} (+1 taken, +1 page cross)", encoding.cycles)
+                } else if encoding.page_penalty {
+                    format!("{} (+1 page cross)", encoding.cycles)
+                } else {
+                    encoding.cycles.to_string()
+                };
+                format!(
+                    "| {} | `{} {}` | ${:02X} | {} | {} |\n",
+                    encoding.mode.name(),
+                    self.mnemonic,
+                    encoding.mode.syntax(),
+                    encoding.opcode,
+                    encoding.mode.bytes(),
+                    cycles
+                )
+            })
+            .collect::<String>();
+        let branch = if self.is_branch() {
+            "\n\nNot taken: 2 cycles. Taken: 3 cycles, \
+             or 4 when the target is on a different page than the next instruction."
+        } else {
+            ""
+        };
+        format!(
+            "**{}** — {}\n\nFlags: `{}`\n\n\
+             | mode | syntax | opcode | bytes | cycles |\n|---|---|---|---|---|\n{}{}",
+            self.mnemonic, self.description, self.flags, rows, branch
+        )
+    }
+}
+
+pub fn find_instruction(mnemonic: &str) -> Option<&'static Instruction> {
+    INSTRUCTIONS
+        .iter()
+        .find(|instruction| instruction.mnemonic.eq_ignore_ascii_case(mnemonic))
+}
+
+use AddressingMode::*;
+
+const fn enc(mode: AddressingMode, opcode: u8, cycles: u8) -> Encoding {
+    Encoding {
+        mode,
+        opcode,
+        cycles,
+        page_penalty: false,
+    }
+}
+const fn pen(mode: AddressingMode, opcode: u8, cycles: u8) -> Encoding {
+    Encoding {
+        mode,
+        opcode,
+        cycles,
+        page_penalty: true,
+    }
+}
+
+pub static INSTRUCTIONS: &[Instruction] = &[
+    Instruction {
+        mnemonic: "ADC",
+        description: "Add memory to accumulator with carry.",
+        flags: "N V Z C",
+        encodings: &[
+            enc(Immediate, 0x69, 2),
+            enc(ZeroPage, 0x65, 3),
+            enc(ZeroPageX, 0x75, 4),
+            enc(Absolute, 0x6D, 4),
+            pen(AbsoluteX, 0x7D, 4),
+            pen(AbsoluteY, 0x79, 4),
+            enc(IndirectX, 0x61, 6),
+            pen(IndirectY, 0x71, 5),
+        ],
+    },
+    Instruction {
+        mnemonic: "AND",
+        description: "AND memory with accumulator.",
+        flags: "N Z",
+        encodings: &[
+            enc(Immediate, 0x29, 2),
+            enc(ZeroPage, 0x25, 3),
+            enc(ZeroPageX, 0x35, 4),
+            enc(Absolute, 0x2D, 4),
+            pen(AbsoluteX, 0x3D, 4),
+            pen(AbsoluteY, 0x39, 4),
+            enc(IndirectX, 0x21, 6),
+            pen(IndirectY, 0x31, 5),
+        ],
+    },
+    Instruction {
+        mnemonic: "ASL",
+        description: "Shift left one bit (memory or accumulator).",
+        flags: "N Z C",
+        encodings: &[
+            enc(Accumulator, 0x0A, 2),
+            enc(ZeroPage, 0x06, 5),
+            enc(ZeroPageX, 0x16, 6),
+            enc(Absolute, 0x0E, 6),
+            enc(AbsoluteX, 0x1E, 7),
+        ],
+    },
+    Instruction {
+        mnemonic: "BCC",
+        description: "Branch on carry clear.",
+        flags: "-",
+        encodings: &[enc(Relative, 0x90, 2)],
+    },
+    Instruction {
+        mnemonic: "BCS",
+        description: "Branch on carry set.",
+        flags: "-",
+        encodings: &[enc(Relative, 0xB0, 2)],
+    },
+    Instruction {
+        mnemonic: "BEQ",
+        description: "Branch on result zero.",
+        flags: "-",
+        encodings: &[enc(Relative, 0xF0, 2)],
+    },
+    Instruction {
+        mnemonic: "BIT",
+        description: "Test bits in memory with accumulator.",
+        flags: "N V Z",
+        encodings: &[enc(ZeroPage, 0x24, 3), enc(Absolute, 0x2C, 4)],
+    },
+    Instruction {
+        mnemonic: "BMI",
+        description: "Branch on result minus.",
+        flags: "-",
+        encodings: &[enc(Relative, 0x30, 2)],
+    },
+    Instruction {
+        mnemonic: "BNE",
+        description: "Branch on result not zero.",
+        flags: "-",
+        encodings: &[enc(Relative, 0xD0, 2)],
+    },
+    Instruction {
+        mnemonic: "BPL",
+        description: "Branch on result plus.",
+        flags: "-",
+        encodings: &[enc(Relative, 0x10, 2)],
+    },
+    Instruction {
+        mnemonic: "BRK",
+        description: "Force break.",
+        flags: "B I",
+        encodings: &[enc(Implied, 0x00, 7)],
+    },
+    Instruction {
+        mnemonic: "BVC",
+        description: "Branch on overflow clear.",
+        flags: "-",
+        encodings: &[enc(Relative, 0x50, 2)],
+    },
+    Instruction {
+        mnemonic: "BVS",
+        description: "Branch on overflow set.",
+        flags: "-",
+        encodings: &[enc(Relative, 0x70, 2)],
+    },
+    Instruction {
+        mnemonic: "CLC",
+        description: "Clear carry flag.",
+        flags: "C",
+        encodings: &[enc(Implied, 0x18, 2)],
+    },
+    Instruction {
+        mnemonic: "CLD",
+        description: "Clear decimal mode.",
+        flags: "D",
+        encodings: &[enc(Implied, 0xD8, 2)],
+    },
+    Instruction {
+        mnemonic: "CLI",
+        description: "Clear interrupt disable bit.",
+        flags: "I",
+        encodings: &[enc(Implied, 0x58, 2)],
+    },
+    Instruction {
+        mnemonic: "CLV",
+        description: "Clear overflow flag.",
+        flags: "V",
+        encodings: &[enc(Implied, 0xB8, 2)],
+    },
+    Instruction {
+        mnemonic: "CMP",
+        description: "Compare memory with accumulator.",
+        flags: "N Z C",
+        encodings: &[
+            enc(Immediate, 0xC9, 2),
+            enc(ZeroPage, 0xC5, 3),
+            enc(ZeroPageX, 0xD5, 4),
+            enc(Absolute, 0xCD, 4),
+            pen(AbsoluteX, 0xDD, 4),
+            pen(AbsoluteY, 0xD9, 4),
+            enc(IndirectX, 0xC1, 6),
+            pen(IndirectY, 0xD1, 5),
+        ],
+    },
+    Instruction {
+        mnemonic: "CPX",
+        description: "Compare memory with index X.",
+        flags: "N Z C",
+        encodings: &[
+            enc(Immediate, 0xE0, 2),
+            enc(ZeroPage, 0xE4, 3),
+            enc(Absolute, 0xEC, 4),
+        ],
+    },
+    Instruction {
+        mnemonic: "CPY",
+        description: "Compare memory with index Y.",
+        flags: "N Z C",
+        encodings: &[
+            enc(Immediate, 0xC0, 2),
+            enc(ZeroPage, 0xC4, 3),
+            enc(Absolute, 0xCC, 4),
+        ],
+    },
+    Instruction {
+        mnemonic: "DEC",
+        description: "Decrement memory by one.",
+        flags: "N Z",
+        encodings: &[
+            enc(ZeroPage, 0xC6, 5),
+            enc(ZeroPageX, 0xD6, 6),
+            enc(Absolute, 0xCE, 6),
+            enc(AbsoluteX, 0xDE, 7),
+        ],
+    },
+    Instruction {
+        mnemonic: "DEX",
+        description: "Decrement index X by one.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0xCA, 2)],
+    },
+    Instruction {
+        mnemonic: "DEY",
+        description: "Decrement index Y by one.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0x88, 2)],
+    },
+    Instruction {
+        mnemonic: "EOR",
+        description: "Exclusive-OR memory with accumulator.",
+        flags: "N Z",
+        encodings: &[
+            enc(Immediate, 0x49, 2),
+            enc(ZeroPage, 0x45, 3),
+            enc(ZeroPageX, 0x55, 4),
+            enc(Absolute, 0x4D, 4),
+            pen(AbsoluteX, 0x5D, 4),
+            pen(AbsoluteY, 0x59, 4),
+            enc(IndirectX, 0x41, 6),
+            pen(IndirectY, 0x51, 5),
+        ],
+    },
+    Instruction {
+        mnemonic: "INC",
+        description: "Increment memory by one.",
+        flags: "N Z",
+        encodings: &[
+            enc(ZeroPage, 0xE6, 5),
+            enc(ZeroPageX, 0xF6, 6),
+            enc(Absolute, 0xEE, 6),
+            enc(AbsoluteX, 0xFE, 7),
+        ],
+    },
+    Instruction {
+        mnemonic: "INX",
+        description: "Increment index X by one.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0xE8, 2)],
+    },
+    Instruction {
+        mnemonic: "INY",
+        description: "Increment index Y by one.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0xC8, 2)],
+    },
+    Instruction {
+        mnemonic: "JMP",
+        description: "Jump to new location.",
+        flags: "-",
+        encodings: &[enc(Absolute, 0x4C, 3), enc(Indirect, 0x6C, 5)],
+    },
+    Instruction {
+        mnemonic: "JSR",
+        description: "Jump to new location saving return address.",
+        flags: "-",
+        encodings: &[enc(Absolute, 0x20, 6)],
+    },
+    Instruction {
+        mnemonic: "LDA",
+        description: "Load accumulator with memory.",
+        flags: "N Z",
+        encodings: &[
+            enc(Immediate, 0xA9, 2),
+            enc(ZeroPage, 0xA5, 3),
+            enc(ZeroPageX, 0xB5, 4),
+            enc(Absolute, 0xAD, 4),
+            pen(AbsoluteX, 0xBD, 4),
+            pen(AbsoluteY, 0xB9, 4),
+            enc(IndirectX, 0xA1, 6),
+            pen(IndirectY, 0xB1, 5),
+        ],
+    },
+    Instruction {
+        mnemonic: "LDX",
+        description: "Load index X with memory.",
+        flags: "N Z",
+        encodings: &[
+            enc(Immediate, 0xA2, 2),
+            enc(ZeroPage, 0xA6, 3),
+            enc(ZeroPageY, 0xB6, 4),
+            enc(Absolute, 0xAE, 4),
+            pen(AbsoluteY, 0xBE, 4),
+        ],
+    },
+    Instruction {
+        mnemonic: "LDY",
+        description: "Load index Y with memory.",
+        flags: "N Z",
+        encodings: &[
+            enc(Immediate, 0xA0, 2),
+            enc(ZeroPage, 0xA4, 3),
+            enc(ZeroPageX, 0xB4, 4),
+            enc(Absolute, 0xAC, 4),
+            pen(AbsoluteX, 0xBC, 4),
+        ],
+    },
+    Instruction {
+        mnemonic: "LSR",
+        description: "Shift one bit right (memory or accumulator).",
+        flags: "N=0 Z C",
+        encodings: &[
+            enc(Accumulator, 0x4A, 2),
+            enc(ZeroPage, 0x46, 5),
+            enc(ZeroPageX, 0x56, 6),
+            enc(Absolute, 0x4E, 6),
+            enc(AbsoluteX, 0x5E, 7),
+        ],
+    },
+    Instruction {
+        mnemonic: "NOP",
+        description: "No operation.",
+        flags: "-",
+        encodings: &[enc(Implied, 0xEA, 2)],
+    },
+    Instruction {
+        mnemonic: "ORA",
+        description: "OR memory with accumulator.",
+        flags: "N Z",
+        encodings: &[
+            enc(Immediate, 0x09, 2),
+            enc(ZeroPage, 0x05, 3),
+            enc(ZeroPageX, 0x15, 4),
+            enc(Absolute, 0x0D, 4),
+            pen(AbsoluteX, 0x1D, 4),
+            pen(AbsoluteY, 0x19, 4),
+            enc(IndirectX, 0x01, 6),
+            pen(IndirectY, 0x11, 5),
+        ],
+    },
+    Instruction {
+        mnemonic: "PHA",
+        description: "Push accumulator on stack.",
+        flags: "-",
+        encodings: &[enc(Implied, 0x48, 3)],
+    },
+    Instruction {
+        mnemonic: "PHP",
+        description: "Push processor status on stack.",
+        flags: "-",
+        encodings: &[enc(Implied, 0x08, 3)],
+    },
+    Instruction {
+        mnemonic: "PLA",
+        description: "Pull accumulator from stack.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0x68, 4)],
+    },
+    Instruction {
+        mnemonic: "PLP",
+        description: "Pull processor status from stack.",
+        flags: "N V D I Z C",
+        encodings: &[enc(Implied, 0x28, 4)],
+    },
+    Instruction {
+        mnemonic: "ROL",
+        description: "Rotate one bit left (memory or accumulator).",
+        flags: "N Z C",
+        encodings: &[
+            enc(Accumulator, 0x2A, 2),
+            enc(ZeroPage, 0x26, 5),
+            enc(ZeroPageX, 0x36, 6),
+            enc(Absolute, 0x2E, 6),
+            enc(AbsoluteX, 0x3E, 7),
+        ],
+    },
+    Instruction {
+        mnemonic: "ROR",
+        description: "Rotate one bit right (memory or accumulator).",
+        flags: "N Z C",
+        encodings: &[
+            enc(Accumulator, 0x6A, 2),
+            enc(ZeroPage, 0x66, 5),
+            enc(ZeroPageX, 0x76, 6),
+            enc(Absolute, 0x6E, 6),
+            enc(AbsoluteX, 0x7E, 7),
+        ],
+    },
+    Instruction {
+        mnemonic: "RTI",
+        description: "Return from interrupt.",
+        flags: "N V D I Z C",
+        encodings: &[enc(Implied, 0x40, 6)],
+    },
+    Instruction {
+        mnemonic: "RTS",
+        description: "Return from subroutine.",
+        flags: "-",
+        encodings: &[enc(Implied, 0x60, 6)],
+    },
+    Instruction {
+        mnemonic: "SBC",
+        description: "Subtract memory from accumulator with borrow.",
+        flags: "N V Z C",
+        encodings: &[
+            enc(Immediate, 0xE9, 2),
+            enc(ZeroPage, 0xE5, 3),
+            enc(ZeroPageX, 0xF5, 4),
+            enc(Absolute, 0xED, 4),
+            pen(AbsoluteX, 0xFD, 4),
+            pen(AbsoluteY, 0xF9, 4),
+            enc(IndirectX, 0xE1, 6),
+            pen(IndirectY, 0xF1, 5),
+        ],
+    },
+    Instruction {
+        mnemonic: "SEC",
+        description: "Set carry flag.",
+        flags: "C",
+        encodings: &[enc(Implied, 0x38, 2)],
+    },
+    Instruction {
+        mnemonic: "SED",
+        description: "Set decimal flag (no effect on the 2A03).",
+        flags: "D",
+        encodings: &[enc(Implied, 0xF8, 2)],
+    },
+    Instruction {
+        mnemonic: "SEI",
+        description: "Set interrupt disable status.",
+        flags: "I",
+        encodings: &[enc(Implied, 0x78, 2)],
+    },
+    Instruction {
+        mnemonic: "STA",
+        description: "Store accumulator in memory.",
+        flags: "-",
+        encodings: &[
+            enc(ZeroPage, 0x85, 3),
+            enc(ZeroPageX, 0x95, 4),
+            enc(Absolute, 0x8D, 4),
+            enc(AbsoluteX, 0x9D, 5),
+            enc(AbsoluteY, 0x99, 5),
+            enc(IndirectX, 0x81, 6),
+            enc(IndirectY, 0x91, 6),
+        ],
+    },
+    Instruction {
+        mnemonic: "STX",
+        description: "Store index X in memory.",
+        flags: "-",
+        encodings: &[
+            enc(ZeroPage, 0x86, 3),
+            enc(ZeroPageY, 0x96, 4),
+            enc(Absolute, 0x8E, 4),
+        ],
+    },
+    Instruction {
+        mnemonic: "STY",
+        description: "Store index Y in memory.",
+        flags: "-",
+        encodings: &[
+            enc(ZeroPage, 0x84, 3),
+            enc(ZeroPageX, 0x94, 4),
+            enc(Absolute, 0x8C, 4),
+        ],
+    },
+    Instruction {
+        mnemonic: "TAX",
+        description: "Transfer accumulator to index X.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0xAA, 2)],
+    },
+    Instruction {
+        mnemonic: "TAY",
+        description: "Transfer accumulator to index Y.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0xA8, 2)],
+    },
+    Instruction {
+        mnemonic: "TSX",
+        description: "Transfer stack pointer to index X.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0xBA, 2)],
+    },
+    Instruction {
+        mnemonic: "TXA",
+        description: "Transfer index X to accumulator.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0x8A, 2)],
+    },
+    Instruction {
+        mnemonic: "TXS",
+        description: "Transfer index X to stack pointer.",
+        flags: "-",
+        encodings: &[enc(Implied, 0x9A, 2)],
+    },
+    Instruction {
+        mnemonic: "TYA",
+        description: "Transfer index Y to accumulator.",
+        flags: "N Z",
+        encodings: &[enc(Implied, 0x98, 2)],
+    },
+];
@@ -0,0 +1,128 @@
+use tower_lsp::lsp_types::{Position, Range};
+
+// 2C02 PPU colours, indexed by NES palette value
+pub static NES_PALETTE: [[u8; 3]; 64] = [
+    [0x7C, 0x7C, 0x7C],
+    [0x00, 0x00, 0xFC],
+    [0x00, 0x00, 0xBC],
+    [0x44, 0x28, 0xBC],
+    [0x94, 0x00, 0x84],
+    [0xA8, 0x00, 0x20],
+    [0xA8, 0x10, 0x00],
+    [0x88, 0x14, 0x00],
+    [0x50, 0x30, 0x00],
+    [0x00, 0x78, 0x00],
+    [0x00, 0x68, 0x00],
+    [0x00, 0x58, 0x00],
+    [0x00, 0x40, 0x58],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0xBC, 0xBC, 0xBC],
+    [0x00, 0x78, 0xF8],
+    [0x00, 0x58, 0xF8],
+    [0x68, 0x44, 0xFC],
+    [0xD8, 0x00, 0xCC],
+    [0xE4, 0x00, 0x58],
+    [0xF8, 0x38, 0x00],
+    [0xE4, 0x5C, 0x10],
+    [0xAC, 0x7C, 0x00],
+    [0x00, 0xB8, 0x00],
+    [0x00, 0xA8, 0x00],
+    [0x00, 0xA8, 0x44],
+    [0x00, 0x88, 0x88],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0xF8, 0xF8, 0xF8],
+    [0x3C, 0xBC, 0xFC],
+    [0x68, 0x88, 0xFC],
+    [0x98, 0x78, 0xF8],
+    [0xF8, 0x78, 0xF8],
+    [0xF8, 0x58, 0x98],
+    [0xF8, 0x78, 0x58],
+    [0xFC, 0xA0, 0x44],
+    [0xF8, 0xB8, 0x00],
+    [0xB8, 0xF8, 0x18],
+    [0x58, 0xD8, 0x54],
+    [0x58, 0xF8, 0x98],
+    [0x00, 0xE8, 0xD8],
+    [0x78, 0x78, 0x78],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0xFC, 0xFC, 0xFC],
+    [0xA4, 0xE4, 0xFC],
+    [0xB8, 0xB8, 0xF8],
+    [0xD8, 0xB8, 0xF8],
+    [0xF8, 0xB8, 0xF8],
+    [0xF8, 0xA4, 0xC0],
+    [0xF0, 0xD0, 0xB0],
+    [0xFC, 0xE0, 0xA8],
+    [0xF8, 0xD8, 0x78],
+    [0xD8, 0xF8, 0x78],
+    [0xB8, 0xF8, 0xB8],
+    [0xB8, 0xF8, 0xD8],
+    [0x00, 0xFC, 0xFC],
+    [0xF8, 0xD8, 0xF8],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+];
+
+// black, dark gray, light gray, white
+pub static GRAYSCALE_PALETTE: [u8; 4] = [0x0F, 0x00, 0x10, 0x30];
+
+pub fn rgb(index: u8) -> [u8; 3] {
+    NES_PALETTE[(index & 0x3F) as usize]
+}
+
+// nearest entry by squared RGB distance; $0D is skipped since it upsets some TVs
+pub fn nearest_index(color: [u8; 3]) -> u8 {
+    let distance = |index: u8| {
+        rgb(index)
+            .iter()
+            .zip(color.iter())
+            .map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+            .sum::<i32>()
+    };
+    (0..64)
+        .filter(|index| *index != 0x0D)
+        .fold(0x0F, |best, index| {
+            if distance(index) < distance(best) {
+                index
+            } else {
+                best
+            }
+        })
+}
+
+// `$xx` literals that are valid palette values
+pub fn collect_color_literals(row: usize, line: &str) -> Vec<(Range, u8)> {
+    let code = match line.find("//") {
+        Some(index) => &line[..index],
+        None => line,
+    };
+    let bytes = code.as_bytes();
+    let is_identifier_byte = |byte: u8| byte.is_ascii_alphanumeric() || byte == b'_';
+    code.match_indices('$')
+        .filter_map(|(offset, _)| {
+            let digits = code.get(offset + 1..offset + 3)?;
+            if offset > 0 && is_identifier_byte(bytes[offset - 1]) {
+                return None;
+            }
+            if bytes
+                .get(offset + 3)
+                .is_some_and(|byte| is_identifier_byte(*byte))
+            {
+                return None;
+            }
+            let index = u8::from_str_radix(digits, 16)
+                .ok()
+                .filter(|index| *index < 64)?;
+            let range = Range {
+                start: Position::new(row as u32, offset as u32),
+                end: Position::new(row as u32, (offset + 3) as u32),
+            };
+            Some((range, index))
+        })
+        .collect()
+}
@@ -0,0 +1,189 @@
+use crate::palette::rgb;
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::path::Path;
+
+// keeps hover payloads small for large sheets
+const MAX_PREVIEW_SIZE: usize = 256;
+const ASCII_PREVIEW_WIDTH: usize = 64;
+const ASCII_PREVIEW_HEIGHT: usize = 16;
+const CHR_TILES_PER_ROW: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct TileSheet {
+    pub width: usize,
+    pub height: usize,
+    pub tile_count: usize,
+    // 2-bit colour indices, row-major
+    pub pixels: Vec<u8>,
+}
+
+impl TileSheet {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("chr") => Ok(Self::from_chr(&bytes)),
+            Some("png") => Self::from_png(&bytes),
+            _ => Err(anyhow!("unsupported tile sheet: {path:?}")),
+        }
+    }
+
+    pub fn from_chr(bytes: &[u8]) -> Self {
+        let tile_count = bytes.len() / 16;
+        let columns = tile_count.clamp(1, CHR_TILES_PER_ROW);
+        let rows = tile_count.div_ceil(columns);
+        let width = columns * 8;
+        let height = rows * 8;
+        let mut pixels = vec![0; width * height];
+        for (tile, planes) in bytes.chunks_exact(16).enumerate() {
+            let (tile_x, tile_y) = ((tile % columns) * 8, (tile / columns) * 8);
+            for y in 0..8 {
+                for x in 0..8 {
+                    let bit = 7 - x;
+                    let low = (planes[y] >> bit) & 1;
+                    let high = (planes[y + 8] >> bit) & 1;
+                    pixels[(tile_y + y) * width + tile_x + x] = low | (high << 1);
+                }
+            }
+        }
+        TileSheet {
+            width,
+            height,
+            tile_count,
+            pixels,
+        }
+    }
+
+    pub fn from_png(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::IDENTITY);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer)?;
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let depth = frame.bit_depth as usize;
+        let pixels = match frame.color_type {
+            png::ColorType::Indexed | png::ColorType::Grayscale if depth <= 8 => {
+                let max = (1 << depth) - 1;
+                (0..height)
+                    .flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .map(|(x, y)| {
+                        let bit = x * depth;
+                        let byte = buffer[y * frame.line_size + bit / 8];
+                        let value = (byte >> (8 - depth - bit % 8)) as usize & max;
+                        if frame.color_type == png::ColorType::Indexed {
+                            (value & 3) as u8
+                        } else {
+                            ((value * 3 + max / 2) / max) as u8
+                        }
+                    })
+                    .collect()
+            }
+            _ => {
+                // true-colour images are reduced to four shades by luminance
+                let mut decoder = png::Decoder::new(bytes);
+                decoder.set_transformations(
+                    png::Transformations::EXPAND | png::Transformations::STRIP_16,
+                );
+                let mut reader = decoder.read_info()?;
+                let mut buffer = vec![0; reader.output_buffer_size()];
+                let frame = reader.next_frame(&mut buffer)?;
+                let channels = frame.color_type.samples();
+                buffer[..frame.buffer_size()]
+                    .chunks_exact(channels)
+                    .map(|pixel| {
+                        let luminance = match pixel {
+                            [red, green, blue, ..] if channels >= 3 => {
+                                (*red as u32 * 3 + *green as u32 * 6 + *blue as u32) / 10
+                            }
+                            _ => pixel[0] as u32,
+                        };
+                        (luminance * 4 / 256) as u8
+                    })
+                    .collect()
+            }
+        };
+        Ok(TileSheet {
+            width,
+            height,
+            tile_count: (width / 8) * (height / 8),
+            pixels,
+        })
+    }
+
+    pub fn to_png(&self, palette: &[u8; 4]) -> anyhow::Result<Vec<u8>> {
+        // wide and tall sheets are cropped to the top left corner
+        let width = self.width.min(MAX_PREVIEW_SIZE);
+        let height = self.height.min(MAX_PREVIEW_SIZE);
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(
+            palette
+                .iter()
+                .flat_map(|index| rgb(*index))
+                .collect::<Vec<_>>(),
+        );
+        let mut writer = encoder.write_header()?;
+        let pixels = self
+            .pixels
+            .chunks(self.width.max(1))
+            .take(height)
+            .flat_map(|row| &row[..width])
+            .copied()
+            .collect::<Vec<_>>();
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
+        Ok(bytes)
+    }
+
+    pub fn to_data_uri(&self, palette: &[u8; 4]) -> anyhow::Result<String> {
+        let bytes = self.to_png(palette).context("failed to encode preview")?;
+        Ok(format!("data:image/png;base64,{}", STANDARD.encode(bytes)))
+    }
+
+    pub fn to_ascii(&self) -> String {
+        self.pixels
+            .chunks(self.width.max(1))
+            .take(ASCII_PREVIEW_HEIGHT)
+            .map(|row| {
+                row.iter()
+                    .take(ASCII_PREVIEW_WIDTH)
+                    .map(|pixel| [' ', '.', '+', '#'][*pixel as usize & 3])
+                    .collect::<String>()
+                    + "\n"
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_png_crops_both_dimensions() {
+        let (width, height) = (MAX_PREVIEW_SIZE + 41, MAX_PREVIEW_SIZE + 8);
+        let sheet = TileSheet {
+            width,
+            height,
+            tile_count: (width / 8) * (height / 8),
+            pixels: (0..width * height).map(|pixel| (pixel % 4) as u8).collect(),
+        };
+        let png = sheet.to_png(&[0x0f, 0x00, 0x10, 0x30]).unwrap();
+        let decoded = TileSheet::from_png(&png).unwrap();
+        assert_eq!(
+            (decoded.width, decoded.height),
+            (MAX_PREVIEW_SIZE, MAX_PREVIEW_SIZE)
+        );
+        // every row starts where the same row of the sheet does
+        assert_eq!(
+            decoded.pixels[MAX_PREVIEW_SIZE..MAX_PREVIEW_SIZE * 2],
+            sheet.pixels[width..width + MAX_PREVIEW_SIZE]
+        );
+    }
+}
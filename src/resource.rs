@@ -0,0 +1,112 @@
+use crate::utf16::utf16_len;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{Position, Range};
+
+// file formats accepted by `file(fmt, "path")` and the extensions they read;
+// an empty list accepts any extension
+pub static FILE_FORMATS: &[(&str, &[&str])] = &[
+    ("raw", &[]),
+    ("pbz", &[]),
+    ("rlz", &[]),
+    ("txt", &["txt"]),
+    ("mapfab", &["mapfab"]),
+    ("spr_8x8", &["png"]),
+    ("spr_8x16", &["png"]),
+];
+
+#[derive(Debug, Clone)]
+pub struct FileInclude {
+    pub format: String,
+    pub format_range: Range,
+    pub path: String,
+    // range of the path inside the quotes
+    pub path_range: Range,
+}
+
+impl FileInclude {
+    pub fn is_known_format(&self) -> bool {
+        FILE_FORMATS.iter().any(|(name, _)| *name == self.format)
+    }
+
+    pub fn is_supported_extension(&self) -> bool {
+        let extension = Path::new(&self.path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        FILE_FORMATS
+            .iter()
+            .filter(|(name, _)| *name == self.format)
+            .any(|(_, extensions)| {
+                extensions.is_empty()
+                    || extension
+                        .as_ref()
+                        .is_some_and(|extension| extensions.contains(&extension.as_str()))
+            })
+    }
+}
+
+fn position_at(source: &str, offset: usize) -> Position {
+    let before = &source[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+    Position::new(line as u32, utf16_len(&before[line_start..]))
+}
+
+// scans for `file(fmt, "path")` expressions
+pub fn collect_file_includes(source: &str) -> Vec<FileInclude> {
+    let bytes = source.as_bytes();
+    let is_identifier_byte = |byte: u8| byte.is_ascii_alphanumeric() || byte == b'_';
+    source
+        .match_indices("file")
+        .filter(|(offset, _)| *offset == 0 || !is_identifier_byte(bytes[offset - 1]))
+        .filter_map(|(offset, keyword)| {
+            let rest = &source[offset + keyword.len()..];
+            let arguments = rest.trim_start().strip_prefix('(')?;
+            let format_start = source.len() - arguments.trim_start().len();
+            let format_end = format_start
+                + source[format_start..]
+                    .bytes()
+                    .take_while(|byte| is_identifier_byte(*byte))
+                    .count();
+            let rest = source[format_end..].trim_start().strip_prefix(',')?;
+            let path = rest.trim_start().strip_prefix('"')?;
+            let path_start = source.len() - path.len();
+            let path_end = path_start + path.find(['"', '\n'])?;
+            if bytes[path_end] != b'"' || format_start == format_end {
+                return None;
+            }
+            Some(FileInclude {
+                format: source[format_start..format_end].to_string(),
+                format_range: Range {
+                    start: position_at(source, format_start),
+                    end: position_at(source, format_end),
+                },
+                path: source[path_start..path_end].to_string(),
+                path_range: Range {
+                    start: position_at(source, path_start),
+                    end: position_at(source, path_end),
+                },
+            })
+        })
+        .collect()
+}
+
+// candidate directories in lookup order: the including file's directory, then resource dirs
+pub fn search_dirs(file_path: &Path, resource_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    file_path
+        .parent()
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(resource_dirs.iter().cloned())
+        .collect()
+}
+
+pub fn resolve_file_include(
+    file_path: &Path,
+    resource_dirs: &[PathBuf],
+    path: &str,
+) -> Option<PathBuf> {
+    search_dirs(file_path, resource_dirs)
+        .into_iter()
+        .map(|dir| dir.join(path))
+        .find(|path| path.is_file())
+}
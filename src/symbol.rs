@@ -1,12 +1,25 @@
+use crate::{asm::parse_asm_function, charmap::parse_charmap_definition};
 use anyhow::Context;
 use std::collections::HashMap;
 use tower_lsp::lsp_types::{Position, Range};
 use tree_sitter::{Node, Parser, TreeCursor};
 
+// definitions indexed as functions, since modes and interrupt handlers are called like them
+pub const FUNCTION_KINDS: &[&str] = &[
+    "function_definition",
+    "asm_function_definition",
+    "mode_definition",
+    "nmi_definition",
+    "irq_definition",
+];
+
 #[derive(Debug, Default, Clone)]
 pub struct SymbolTable {
     pub functions: HashMap<String, FunctionSymbol>,
     pub global_variables: HashMap<String, VariableSymbol>,
+    pub charmaps: HashMap<String, CharmapSymbol>,
+    // earlier definitions replaced by a later one with the same name
+    pub shadowed: Vec<(String, Range)>,
 }
 impl SymbolTable {
     pub fn from_source(source: &str) -> anyhow::Result<Self> {
@@ -22,6 +35,26 @@ impl SymbolTable {
         traverse_tree(source, &mut cursor, &mut symbol_table)?;
         Ok(symbol_table)
     }
+    pub fn definition_ranges(&self, name: &str) -> Vec<Range> {
+        self.shadowed
+            .iter()
+            .filter(|(shadowed, _)| shadowed == name)
+            .map(|(_, range)| *range)
+            .chain(self.functions.get(name).map(|symbol| symbol.range))
+            .chain(self.global_variables.get(name).map(|symbol| symbol.range))
+            .collect()
+    }
+    // a function and a global with the same name are both kept, definition_ranges reports them
+    fn insert_function(&mut self, symbol: FunctionSymbol) {
+        if let Some(previous) = self.functions.insert(symbol.name.clone(), symbol) {
+            self.shadowed.push((previous.name, previous.range));
+        }
+    }
+    fn insert_global_variable(&mut self, symbol: VariableSymbol) {
+        if let Some(previous) = self.global_variables.insert(symbol.name.clone(), symbol) {
+            self.shadowed.push((previous.name, previous.range));
+        }
+    }
     pub fn find_symbol(&self, node: &Node, name: &str) -> anyhow::Result<Box<dyn Symbol>> {
         let parent = node.parent().context("faield to get parent")?;
         let symbol = match parent.kind() {
@@ -30,7 +63,7 @@ impl SymbolTable {
                 .get(name)
                 .map(|s| Box::new(s.to_owned()) as Box<dyn Symbol>),
             _ => match parent.parent().context("failed to get parent")?.kind() {
-                "function_definition" | "asm_function_definition" => self
+                kind if FUNCTION_KINDS.contains(&kind) => self
                     .functions
                     .get(name)
                     .map(|s| Box::new(s.to_owned()) as Box<dyn Symbol>),
@@ -64,6 +97,7 @@ pub struct FunctionSymbol {
     // modifiers: Vec<ModifierSymbol>,
     // local_variables: Vec<VariableSymbol>,
     pub comments: Option<String>,
+    pub labels: Vec<LabelSymbol>,
 }
 
 impl Symbol for FunctionSymbol {
@@ -105,6 +139,19 @@ impl Symbol for FunctionSymbol {
                 node_range.end_point.column as u32,
             ),
         };
+        let labels = if node.kind() == "asm_function_definition" {
+            parse_asm_function(source, node)
+                .into_iter()
+                .filter_map(|line| line.label)
+                .map(|label| LabelSymbol {
+                    description: format!("label {}", label.text),
+                    name: label.text,
+                    range: label.range,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
 
         Ok(FunctionSymbol {
             name: name.to_string(),
@@ -112,6 +159,7 @@ impl Symbol for FunctionSymbol {
             description,
             signature,
             comments,
+            labels,
         })
     }
     fn range(&self) -> Range {
@@ -183,7 +231,63 @@ impl Symbol for VariableSymbol {
     }
 }
 
-fn collect_sibling_comment_nodes(node: Node) -> Vec<Node> {
+#[derive(Debug, Default, Clone)]
+pub struct LabelSymbol {
+    pub range: Range,
+    pub description: String,
+
+    pub name: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CharmapSymbol {
+    pub range: Range,
+    pub description: String,
+
+    pub name: String,
+    pub characters: Vec<char>,
+    pub sentinel: Option<char>,
+    pub is_default: bool,
+}
+
+impl CharmapSymbol {
+    pub fn from_line(row: usize, line: &str) -> Option<Self> {
+        let definition = parse_charmap_definition(row, line)?;
+        Some(CharmapSymbol {
+            range: definition.name_range,
+            description: line.trim().to_string(),
+            name: definition.name,
+            characters: definition.characters,
+            sentinel: definition.sentinel,
+            is_default: definition.is_default,
+        })
+    }
+    pub fn index(&self, character: char) -> Option<usize> {
+        self.characters.iter().position(|c| *c == character)
+    }
+    // None for characters past the 256 a byte can encode, as well as unknown ones
+    pub fn encode(&self, character: char) -> Option<u8> {
+        self.index(character)
+            .and_then(|index| u8::try_from(index).ok())
+    }
+}
+
+impl Symbol for CharmapSymbol {
+    fn from_node(source: &str, node: &Node) -> anyhow::Result<Self> {
+        let row = node.start_position().row;
+        let line = source.lines().nth(row).context("failed to get line")?;
+        CharmapSymbol::from_line(row, line)
+            .context(format!("failed to get charmap: {:?}", node.byte_range()))
+    }
+    fn range(&self) -> Range {
+        self.range.to_owned()
+    }
+    fn description(&self) -> &str {
+        self.description.as_str()
+    }
+}
+
+pub fn collect_sibling_comment_nodes(node: Node) -> Vec<Node> {
     let mut comments = Vec::new();
     let mut pivot_line_number = node.start_position().row as isize;
     let mut pivot = Some(node);
@@ -214,18 +318,27 @@ pub fn traverse_tree(
         let node = cursor.node();
         if node.is_named() {
             match node.kind() {
+                "charmap_definition" => {
+                    // charmaps the line parser can't read leave the rest of the file indexed
+                    if let Ok(symbol) = CharmapSymbol::from_node(source, &node) {
+                        symbol_table.charmaps.insert(symbol.name.clone(), symbol);
+                    }
+                }
                 "function_definition" | "asm_function_definition" => {
                     let symbol = FunctionSymbol::from_node(source, &node)?;
-                    symbol_table.functions.insert(symbol.name.clone(), symbol);
+                    symbol_table.insert_function(symbol);
+                }
+                "mode_definition" | "nmi_definition" | "irq_definition" => {
+                    if let Ok(symbol) = FunctionSymbol::from_node(source, &node) {
+                        symbol_table.insert_function(symbol);
+                    }
                 }
                 "variable_definition" => {
                     // check global variable only
                     if let Some(parent) = node.parent() {
                         if parent.kind() == "module" || parent.kind() == "vars_definition" {
                             let symbol = VariableSymbol::from_node(source, &node)?;
-                            symbol_table
-                                .global_variables
-                                .insert(symbol.name.clone(), symbol);
+                            symbol_table.insert_global_variable(symbol);
                         }
                     }
                 }
@@ -242,3 +355,26 @@ pub fn traverse_tree(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_charmap_keeps_other_definitions() {
+        let source = "\
+charmap broken(
+    \" abc\")
+
+fn update()
+    return
+
+vars
+    U x = 1
+";
+        let symbol_table = SymbolTable::from_source(source).unwrap();
+        assert!(symbol_table.charmaps.is_empty());
+        assert!(symbol_table.functions.contains_key("update"));
+        assert!(symbol_table.global_variables.contains_key("x"));
+    }
+}
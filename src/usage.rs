@@ -0,0 +1,496 @@
+use crate::{
+    asm::parse_asm_function,
+    symbol::FUNCTION_KINDS,
+    utf16::{utf16_len, utf16_range},
+};
+use std::collections::{HashMap, HashSet};
+use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::{Node, Tree};
+
+#[derive(Debug, Clone)]
+pub struct IdentifierToken {
+    pub text: String,
+    pub range: Range,
+    // preceded by `.`, i.e. a struct field access
+    pub is_field: bool,
+    // the target of `=` or a compound assignment
+    pub is_write: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructField {
+    pub struct_name: String,
+    pub name: String,
+    // declared type, without array dimensions
+    pub type_name: String,
+    pub range: Range,
+    pub row: u32,
+}
+
+const ASSIGNMENT_OPERATORS: &[&str] =
+    &["=", "+=", "-=", "*=", "/=", "&=", "|=", "^=", "<<=", ">>="];
+
+fn is_field(node: &Node) -> bool {
+    node.kind() == "field_identifier"
+        || node
+            .prev_sibling()
+            .is_some_and(|previous| previous.kind() == ".")
+}
+
+// followed by an assignment operator, either directly or as the end of the assigned expression
+fn is_assigned(node: Node) -> bool {
+    let mut node = node;
+    loop {
+        if let Some(next) = node.next_sibling() {
+            return ASSIGNMENT_OPERATORS.contains(&next.kind());
+        }
+        match node.parent() {
+            Some(parent) => node = parent,
+            None => return false,
+        }
+    }
+}
+
+pub fn identifier_token(source: &str, node: Node) -> Option<IdentifierToken> {
+    Some(IdentifierToken {
+        text: node.utf8_text(source.as_bytes()).ok()?.to_string(),
+        range: utf16_range(source, &node),
+        is_field: is_field(&node),
+        is_write: is_assigned(node),
+    })
+}
+
+fn collect_identifier_nodes<'a>(tree: &'a Tree) -> Vec<Node<'a>> {
+    let mut nodes = Vec::new();
+    collect_nodes(
+        tree.root_node(),
+        &["identifier", "field_identifier"],
+        &mut nodes,
+    );
+    nodes
+}
+
+pub fn collect_identifier_tokens(source: &str, tree: &Tree) -> Vec<IdentifierToken> {
+    collect_identifier_nodes(tree)
+        .into_iter()
+        .filter_map(|node| identifier_token(source, node))
+        .collect()
+}
+
+// asm bodies are read line by line, so names in their operands aren't identifier nodes
+fn collect_asm_operand_tokens(source: &str, tree: &Tree) -> Vec<IdentifierToken> {
+    let lines = source.lines().collect::<Vec<_>>();
+    let mut asm_functions = Vec::new();
+    collect_nodes(
+        tree.root_node(),
+        &["asm_function_definition"],
+        &mut asm_functions,
+    );
+    let mut tokens = Vec::new();
+    for node in asm_functions {
+        for asm_line in parse_asm_function(source, &node) {
+            let (Some(operand), Some(line)) = (asm_line.operand, lines.get(asm_line.row)) else {
+                continue;
+            };
+            // `lda foo+1,x` reads `foo`, the index register after the comma isn't a name
+            let address = operand.text.split(',').next().unwrap_or_default();
+            let start = operand.range.start.character as usize;
+            let mut offset = 0;
+            for word in address.split(|c: char| !c.is_ascii_alphanumeric() && c != '_') {
+                let word_start = start + offset;
+                offset += word.len() + 1;
+                if !word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                    continue;
+                }
+                let column = |byte: usize| utf16_len(line.get(..byte).unwrap_or_default());
+                let row = asm_line.row as u32;
+                tokens.push(IdentifierToken {
+                    text: word.to_string(),
+                    range: Range::new(
+                        Position::new(row, column(word_start)),
+                        Position::new(row, column(word_start + word.len())),
+                    ),
+                    is_field: false,
+                    is_write: false,
+                });
+            }
+        }
+    }
+    tokens
+}
+
+// fields are declared like variables inside the body of a `struct` definition
+pub fn collect_struct_fields(source: &str, tree: &Tree) -> Vec<StructField> {
+    let bytes = source.as_bytes();
+    let mut structs = Vec::new();
+    collect_nodes(tree.root_node(), &["struct_definition"], &mut structs);
+    let mut fields = Vec::new();
+    for node in structs {
+        let Some(struct_name) = node
+            .child_by_field_name("name")
+            .and_then(|name| name.utf8_text(bytes).ok())
+        else {
+            continue;
+        };
+        let mut declarations = Vec::new();
+        collect_nodes(
+            node,
+            &["field_definition", "variable_definition"],
+            &mut declarations,
+        );
+        for declaration in declarations {
+            if let Some((name, type_name, range)) = variable_declaration(source, &declaration) {
+                fields.push(StructField {
+                    struct_name: struct_name.to_string(),
+                    name,
+                    type_name,
+                    range,
+                    row: range.start.line,
+                });
+            }
+        }
+    }
+    fields
+}
+
+// `ct P[4]` -> `P`
+fn base_type(declaration: &str) -> String {
+    let declaration = declaration.trim();
+    let declaration = declaration.strip_prefix("ct ").unwrap_or(declaration);
+    let word = declaration.split_whitespace().next().unwrap_or_default();
+    word.split('[').next().unwrap_or_default().to_string()
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldAccess {
+    // identifier the access chain starts from, empty for calls and other expressions
+    pub root: String,
+    // declared type of `root` when it is a local variable or parameter
+    pub local_type: Option<String>,
+    // fields from the root up to the accessed one, which comes last
+    pub path: Vec<String>,
+}
+
+// per-file facts behind unused symbol detection, rebuilt whenever the file is parsed
+#[derive(Debug, Clone, Default)]
+pub struct FileUsage {
+    // names read inside each top-level function, its own locals excluded
+    pub references: HashMap<String, HashSet<String>>,
+    // names read outside every function, such as in global initializers
+    pub top_level_references: HashSet<String>,
+    // modes, nmi and irq handlers and `main` run without being called
+    pub roots: HashSet<String>,
+    // declared type of each global variable
+    pub global_types: HashMap<String, String>,
+    pub struct_fields: Vec<StructField>,
+    pub field_accesses: Vec<FieldAccess>,
+    // range of the name in each function definition
+    pub function_names: HashMap<String, Range>,
+    // (caller, callee, range of the callee name) for every call in the file
+    pub call_sites: Vec<(String, String, Range)>,
+}
+
+struct FunctionScope {
+    name: String,
+    name_range: Range,
+    range: Range,
+    // local variables and parameters with their declared types
+    locals: HashMap<String, String>,
+}
+
+fn start_key(range: &Range) -> (u32, u32) {
+    (range.start.line, range.start.character)
+}
+
+fn contains(outer: &Range, inner: &Range) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+fn collect_nodes<'a>(node: Node<'a>, kinds: &[&str], nodes: &mut Vec<Node<'a>>) {
+    if kinds.contains(&node.kind()) {
+        nodes.push(node);
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_nodes(child, kinds, nodes);
+    }
+}
+
+// name and declared type of a variable definition, with the range of the name
+fn variable_declaration(source: &str, node: &Node) -> Option<(String, String, Range)> {
+    let name = node.child_by_field_name("name")?;
+    let declaration = source.get(node.start_byte()..name.start_byte())?;
+    let text = name.utf8_text(source.as_bytes()).ok()?;
+    Some((
+        text.to_string(),
+        base_type(declaration),
+        utf16_range(source, &name),
+    ))
+}
+
+fn function_scope(
+    source: &str,
+    node: &Node,
+    definition_names: &mut HashSet<(u32, u32)>,
+) -> Option<FunctionScope> {
+    let bytes = source.as_bytes();
+    let signature = node.child_by_field_name("signature")?;
+    let name = signature.child_by_field_name("name")?;
+    definition_names.insert(start_key(&utf16_range(source, &name)));
+    let mut locals = HashMap::new();
+    let mut variables = Vec::new();
+    collect_nodes(*node, &["variable_definition"], &mut variables);
+    for variable in variables {
+        if let Some((name, type_name, range)) = variable_declaration(source, &variable) {
+            definition_names.insert(start_key(&range));
+            locals.insert(name, type_name);
+        }
+    }
+    // parameters are `Type name` items between the signature's parentheses
+    let signature_text = signature.utf8_text(bytes).ok()?;
+    let mut signature_names = Vec::new();
+    collect_nodes(signature, &["identifier"], &mut signature_names);
+    let parameters = signature_text
+        .split_once('(')
+        .and_then(|(_, rest)| rest.rsplit_once(')'))
+        .map(|(parameters, _)| parameters)
+        .unwrap_or_default();
+    for parameter in parameters.split(',') {
+        let Some((declaration, name)) = parameter.trim().rsplit_once(char::is_whitespace) else {
+            continue;
+        };
+        let range = signature_names
+            .iter()
+            .filter(|node| node.utf8_text(bytes).is_ok_and(|text| text == name))
+            .map(|node| utf16_range(source, node))
+            .next_back();
+        definition_names.extend(range.as_ref().map(start_key));
+        locals.insert(name.to_string(), base_type(declaration));
+    }
+    Some(FunctionScope {
+        name: name.utf8_text(bytes).ok()?.to_string(),
+        name_range: utf16_range(source, &name),
+        range: utf16_range(source, node),
+        locals,
+    })
+}
+
+// walks `a.b[i].c` back from the field node `c` to the identifier the chain starts from
+fn field_access(source: &str, field: Node) -> (String, Vec<String>) {
+    let text = |node: Node| {
+        node.utf8_text(source.as_bytes())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let mut path = vec![text(field)];
+    let mut object = field.prev_sibling().and_then(|dot| dot.prev_sibling());
+    while let Some(node) = object {
+        if matches!(node.kind(), "identifier" | "field_identifier") {
+            if !is_field(&node) {
+                return (text(node), path);
+            }
+            path.insert(0, text(node));
+            object = node.prev_sibling().and_then(|dot| dot.prev_sibling());
+            continue;
+        }
+        let Some(last) = node.child(node.child_count().saturating_sub(1)) else {
+            break;
+        };
+        object = if last.kind() == "]" {
+            // array elements share the type of their array
+            node.child(0)
+        } else if matches!(last.kind(), "identifier" | "field_identifier") && is_field(&last) {
+            Some(last)
+        } else {
+            None
+        };
+    }
+    (String::new(), path)
+}
+
+// calls, `goto mode` targets, `: nmi`/`: irq` handlers and asm `jsr`/`jmp` targets
+fn collect_call_sites(
+    source: &str,
+    tree: &Tree,
+    scopes: &[FunctionScope],
+) -> Vec<(String, String, Range)> {
+    let bytes = source.as_bytes();
+    let caller = |range: &Range| {
+        scopes
+            .iter()
+            .find(|scope| contains(&scope.range, range))
+            .map(|scope| scope.name.clone())
+    };
+    let mut sites = Vec::new();
+    let mut calls = Vec::new();
+    collect_nodes(tree.root_node(), &["call"], &mut calls);
+    for node in calls {
+        let Some(callee) = node
+            .named_child(0)
+            .filter(|child| child.kind() == "identifier")
+        else {
+            continue;
+        };
+        let range = utf16_range(source, &callee);
+        if let (Some(caller), Ok(name)) = (caller(&range), callee.utf8_text(bytes)) {
+            sites.push((caller, name.to_string(), range));
+        }
+    }
+    // `goto mode name()` and `: nmi name` name their target after the keyword tokens
+    let mut keywords = Vec::new();
+    collect_nodes(tree.root_node(), &["goto", "nmi", "irq"], &mut keywords);
+    for keyword in keywords {
+        let target = match keyword.kind() {
+            "goto" => keyword
+                .next_sibling()
+                .filter(|next| next.kind() == "mode")
+                .and_then(|mode| mode.next_named_sibling()),
+            // `nmi name()` defining a handler is followed by its signature instead
+            _ => keyword.next_named_sibling(),
+        };
+        let Some(target) = target else {
+            continue;
+        };
+        let name = if target.kind() == "identifier" {
+            Some(target)
+        } else {
+            target
+                .named_child(0)
+                .filter(|child| child.kind() == "identifier" && keyword.kind() == "goto")
+        };
+        let Some(name) = name else {
+            continue;
+        };
+        let range = utf16_range(source, &name);
+        if sites.iter().any(|(_, _, site)| *site == range) {
+            continue;
+        }
+        if let (Some(caller), Ok(text)) = (caller(&range), name.utf8_text(bytes)) {
+            sites.push((caller, text.to_string(), range));
+        }
+    }
+    let mut asm_functions = Vec::new();
+    collect_nodes(
+        tree.root_node(),
+        &["asm_function_definition"],
+        &mut asm_functions,
+    );
+    for node in asm_functions {
+        let Some(caller) = caller(&utf16_range(source, &node)) else {
+            continue;
+        };
+        let lines = parse_asm_function(source, &node);
+        let labels = lines
+            .iter()
+            .filter_map(|line| line.label.as_ref().map(|label| label.text.as_str()))
+            .collect::<HashSet<_>>();
+        for target in lines.iter().filter_map(|line| line.target()) {
+            if !labels.contains(target.text.as_str()) {
+                sites.push((caller.clone(), target.text.clone(), target.range));
+            }
+        }
+    }
+    sites
+}
+
+impl FileUsage {
+    pub fn from_tree(source: &str, tree: &Tree) -> Self {
+        let bytes = source.as_bytes();
+        // asm operands have no node, and can't be field accesses
+        let mut tokens = collect_identifier_nodes(tree)
+            .into_iter()
+            .filter_map(|node| Some((identifier_token(source, node)?, Some(node))))
+            .collect::<Vec<_>>();
+        let mut usage = FileUsage {
+            struct_fields: collect_struct_fields(source, tree),
+            ..Default::default()
+        };
+        // definitions mention their own name, which is not a use
+        let mut definition_names = HashSet::new();
+
+        let mut functions = Vec::new();
+        collect_nodes(tree.root_node(), FUNCTION_KINDS, &mut functions);
+        let mut scopes = Vec::new();
+        for function in functions {
+            let Some(scope) = function_scope(source, &function, &mut definition_names) else {
+                continue;
+            };
+            let text = function.utf8_text(bytes).unwrap_or_default();
+            let keyword = text.split_whitespace().next().unwrap_or_default();
+            let is_entry_point = matches!(
+                function.kind(),
+                "mode_definition" | "nmi_definition" | "irq_definition"
+            ) || matches!(keyword, "mode" | "nmi" | "irq");
+            if is_entry_point || scope.name == "main" {
+                usage.roots.insert(scope.name.clone());
+            }
+            usage.references.entry(scope.name.clone()).or_default();
+            usage
+                .function_names
+                .insert(scope.name.clone(), scope.name_range);
+            scopes.push(scope);
+        }
+        usage.call_sites = collect_call_sites(source, tree, &scopes);
+
+        let mut variables = Vec::new();
+        collect_nodes(tree.root_node(), &["variable_definition"], &mut variables);
+        for variable in variables {
+            let is_global = variable
+                .parent()
+                .is_some_and(|parent| matches!(parent.kind(), "module" | "vars_definition"));
+            if !is_global {
+                continue;
+            }
+            if let Some((name, type_name, range)) = variable_declaration(source, &variable) {
+                definition_names.insert(start_key(&range));
+                usage.global_types.insert(name, type_name);
+            }
+        }
+
+        let asm_tokens = collect_asm_operand_tokens(source, tree)
+            .into_iter()
+            .filter(|asm_token| {
+                !tokens.iter().any(|(token, _)| {
+                    token.text == asm_token.text
+                        && token.range.start.line == asm_token.range.start.line
+                })
+            })
+            .collect::<Vec<_>>();
+        tokens.extend(asm_tokens.into_iter().map(|token| (token, None)));
+        for (token, node) in tokens {
+            if definition_names.contains(&start_key(&token.range)) {
+                continue;
+            }
+            let scope = scopes
+                .iter()
+                .find(|scope| contains(&scope.range, &token.range));
+            if let (true, Some(field)) = (token.is_field, node) {
+                let (root, path) = field_access(source, field);
+                let local_type = scope.and_then(|scope| scope.locals.get(&root).cloned());
+                usage.field_accesses.push(FieldAccess {
+                    root,
+                    local_type,
+                    path,
+                });
+                continue;
+            }
+            if token.is_write {
+                continue;
+            }
+            match scope {
+                Some(scope) if scope.locals.contains_key(&token.text) => {}
+                Some(scope) => {
+                    usage
+                        .references
+                        .entry(scope.name.clone())
+                        .or_default()
+                        .insert(token.text);
+                }
+                None => {
+                    usage.top_level_references.insert(token.text);
+                }
+            }
+        }
+        usage
+    }
+}
@@ -0,0 +1,38 @@
+// LSP columns count UTF-16 code units while Rust strings are indexed by bytes
+
+use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::{Node, Point};
+
+pub fn utf16_len(text: &str) -> u32 {
+    text.chars().map(|c| c.len_utf16() as u32).sum()
+}
+
+// byte offset of a UTF-16 column in `line`, None when it is past the end or splits a char
+pub fn byte_offset(line: &str, column: u32) -> Option<usize> {
+    let mut units = 0;
+    for (offset, c) in line.char_indices() {
+        if units == column {
+            return Some(offset);
+        }
+        if units > column {
+            return None;
+        }
+        units += c.len_utf16() as u32;
+    }
+    (units == column).then_some(line.len())
+}
+
+// `point` is the tree-sitter location of byte `offset`, its column counting bytes
+fn position(source: &str, offset: usize, point: Point) -> Position {
+    let line = source
+        .get(offset.saturating_sub(point.column)..offset)
+        .unwrap_or_default();
+    Position::new(point.row as u32, utf16_len(line))
+}
+
+pub fn utf16_range(source: &str, node: &Node) -> Range {
+    Range::new(
+        position(source, node.start_byte(), node.start_position()),
+        position(source, node.end_byte(), node.end_position()),
+    )
+}